@@ -1,7 +1,7 @@
-export function save_data_url(url) {
+export function save_data_url(url, filename) {
     console.log(url);
     var a = document.createElement('a');
-    a.download = 'download.svg';
+    a.download = filename;
     a.href = url;
     a.click();
-}
\ No newline at end of file
+}
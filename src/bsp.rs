@@ -1,131 +1,128 @@
+//! The tree itself.
+
 use glam::*;
+use serde::{Deserialize, Serialize};
 use slotmap::new_key_type;
-use slotmap::SlotMap;
-
-#[derive(Debug, Clone)]
-pub struct Plane {
-    pub normal: Vec2,
-    pub distance: f32,
+use slotmap::{SecondaryMap, SlotMap};
+
+use crate::geom::{Plane, Polygon};
+
+/// A binary space partition of the plane, with a `T` in every leaf.
+///
+/// A new tree is a single leaf covering everything. Splitting a leaf turns it
+/// into an inode with a [`Plane`] and two child leaves; reading a point walks
+/// down from the root to the leaf whose region contains it.
+///
+/// Nodes live in a slotmap and are addressed by [`BspKey`]. Keys stay valid
+/// until the node they point to is removed, so they can be held on to across
+/// edits elsewhere in the tree. Every node knows its parent, so walking up
+/// from a key is as cheap as walking down.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "BspRepr<T>")]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+pub struct Bsp<T> {
+    nodes: SlotMap<BspKey, BspNode<T>>,
+    root: BspKey,
+    // everything but the root; kept up to date by every edit, and rebuilt
+    // on load rather than trusted from a file
+    #[serde(skip)]
+    parents: SecondaryMap<BspKey, BspKey>,
 }
-impl Plane {
-    pub fn distance_to_point(&self, point: Vec2) -> f32 {
-        point.dot(self.normal) - self.distance
-    }
-
-    pub fn line_intersection(&self, (start, end): (Vec2, Vec2)) -> Option<Vec2> {
-        let start_dist = self.distance_to_point(start);
-        let end_dist = self.distance_to_point(end);
+impl<T> Bsp<T> {
+    /// A tree with a single leaf.
+    pub fn new(root_val: T) -> Bsp<T> {
+        let mut nodes = SlotMap::with_key();
+        let root = nodes.insert(BspNode::Leaf(BspLeaf(root_val)));
 
-        if (start_dist > 0.0) == (end_dist > 0.0) {
-            // both on the same side!
-            println!("{:?} {:?} {:?} {:?}", start_dist, end_dist, start, end);
-            return None;
+        Bsp {
+            nodes,
+            root,
+            parents: SecondaryMap::new(),
         }
+    }
+    /// Number of nodes, inodes and leaves both.
+    // a tree always has its root, so there's no is_empty to go with this
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
 
-        let total_dist = end_dist - start_dist;
+    /// Rough memory footprint, for budgeting things that keep copies around.
+    pub fn approx_size_bytes(&self) -> usize {
+        // slotmap stores a u32 version next to every slot
+        let slot_size = std::mem::size_of::<BspNode<T>>() + std::mem::size_of::<u32>();
+        let parent_size = std::mem::size_of::<BspKey>() + std::mem::size_of::<u32>();
+        std::mem::size_of::<Self>()
+            + self.nodes.capacity() * slot_size
+            + self.parents.capacity() * parent_size
+    }
 
-        let frac = start_dist.abs() / total_dist.abs();
+    /// The node every traversal starts from.
+    pub fn root_key(&self) -> BspKey {
+        self.root
+    }
 
-        Some(start + (end - start) * frac)
+    /// The node at `key`, if it's still in the tree.
+    pub fn get(&self, key: BspKey) -> Option<&BspNode<T>> {
+        self.nodes.get(key)
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Polygon {
-    // wound clockwise
-    pub vertices: Vec<Vec2>,
-}
-impl Polygon {
-    pub fn new_rect(min: Vec2, max: Vec2) -> Polygon {
-        Polygon {
-            vertices: vec![min, Vec2::new(min.x, max.y), max, Vec2::new(max.x, min.y)],
+    /// The value at `key`, if it's a leaf that's still in the tree.
+    pub fn leaf_value(&self, key: BspKey) -> Option<&T> {
+        match self.nodes.get(key) {
+            Some(BspNode::Leaf(l)) => Some(&l.0),
+            _ => None,
         }
     }
-    pub fn clip_against_plane(&self, plane: &Plane, clipside_is_greater: bool) -> Polygon {
-        if self.vertices.len() == 0 {
-            return self.clone();
-        }
-
-        assert!(self.vertices.len() >= 3);
-
-        // we may cross this plane many times,
-        // though always an even number (else bug!)
-
-        // what we really care about are edges between those on the "keep side"
-        // and the "clipped side".
-        // in this code, "clipside" means the side that will be clipped out
-        let cmp_to_clipside = |a: f32, b: f32| {
-            use std::cmp::Ordering;
-
-            match a.partial_cmp(&b).unwrap() {
-                Ordering::Less | Ordering::Equal => !clipside_is_greater,
-                Ordering::Greater => clipside_is_greater,
-            }
-        };
-
-        // this is an overapproximation but whatever
-        let mut new_vertices = Vec::with_capacity(self.vertices.len());
 
-        let mut prev = *self.vertices.last().unwrap();
-        let mut prev_clipside = cmp_to_clipside(plane.distance_to_point(prev), 0.0);
-        for &current in &self.vertices {
-            let current_clipside = cmp_to_clipside(plane.distance_to_point(current), 0.0);
-
-            match (prev_clipside, current_clipside) {
-                (false, false) => {
-                    // both on the keep side
-                    new_vertices.push(current);
-                }
-                (false, true) => {
-                    // we just entered the clip side!
-
-                    // instead of emitting current, we must clip the edge between prev and current
-                    let clipvert = plane.line_intersection((prev, current)).unwrap();
-                    new_vertices.push(clipvert);
-                }
-                (true, false) => {
-                    // we just left the clip side!
-                    // we will emit current, but we must also emit a vertex from the clipped edge
-
-                    let clipvert = plane.line_intersection((prev, current)).unwrap();
-                    new_vertices.push(clipvert);
-
-                    new_vertices.push(current);
-                }
-                (true, true) => {
-                    // both on the clip side. emit no vertices.
-                    ()
-                }
-            }
-            prev = current;
-            prev_clipside = current_clipside;
+    /// Mutable access to the value at `key`, if it's a leaf.
+    pub fn leaf_value_mut(&mut self, key: BspKey) -> Option<&mut T> {
+        match self.nodes.get_mut(key) {
+            Some(BspNode::Leaf(l)) => Some(&mut l.0),
+            _ => None,
         }
+    }
 
-        Polygon {
-            vertices: new_vertices,
+    /// Mutable access to the plane at `key`, if it's an inode. Moving it
+    /// changes the regions of everything below `key`.
+    pub fn plane_mut(&mut self, key: BspKey) -> Option<&mut Plane> {
+        match self.nodes.get_mut(key) {
+            Some(BspNode::Inode(inode)) => Some(&mut inode.plane),
+            _ => None,
         }
     }
-}
 
-pub struct Bsp<T> {
-    nodes: SlotMap<BspKey, BspNode<T>>,
-    root: BspKey,
-}
-impl<T> Bsp<T> {
-    pub fn new(root_val: T) -> Bsp<T> {
-        let mut nodes = SlotMap::with_key();
-        let root = nodes.insert(BspNode::Leaf(BspLeaf(root_val)));
+    /// The inode `key` is a child of, or `None` for the root (and for keys
+    /// that aren't in the tree).
+    pub fn parent(&self, key: BspKey) -> Option<BspKey> {
+        self.parents.get(key).copied()
+    }
 
-        Bsp { nodes, root }
+    /// The other child of `key`'s parent.
+    pub fn sibling(&self, key: BspKey) -> Option<BspKey> {
+        match self.nodes.get(self.parent(key)?) {
+            Some(BspNode::Inode(inode)) => Some(if inode.le == key { inode.gt } else { inode.le }),
+            _ => None,
+        }
     }
-    pub fn len(&self) -> usize {
-        self.nodes.len()
+
+    /// Every inode above `key`, from its parent up to the root.
+    pub fn ancestors(&self, key: BspKey) -> impl Iterator<Item = BspKey> + '_ {
+        std::iter::successors(self.parent(key), move |&key| self.parent(key))
     }
 
-    pub fn root_key(&self) -> BspKey {
-        self.root.clone()
+    /// How many inodes are above `key`; the root is at depth 0.
+    pub fn depth(&self, key: BspKey) -> usize {
+        self.ancestors(key).count()
     }
 
+    /// Every node in the tree, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (BspKey, &BspNode<T>)> {
+        self.nodes.iter()
+    }
+
+    /// Calls `cb` for every node on the path from the root down to the leaf
+    /// containing `point`, root first and leaf last.
     pub fn visit_leaf_ancestors<F>(&self, point: glam::Vec2, mut cb: F)
         where F: FnMut(BspKey, &BspNode<T>) {
 
@@ -150,6 +147,8 @@ impl<T> Bsp<T> {
         }     
     }
 
+    /// The leaf whose region contains `point`. Points exactly on a plane
+    /// belong to its "le" side.
     pub fn leaf_index_for_point(&self, point: glam::Vec2) -> BspKey {
         let mut node = self.root_key();
 
@@ -169,12 +168,14 @@ impl<T> Bsp<T> {
             }
         }
     }
+    /// The value of the leaf containing `point`.
     pub fn get_at_point(&self, point: Vec2) -> &T {
         match &self.nodes[self.leaf_index_for_point(point)] {
             BspNode::Inode(_) => unreachable!(),
             BspNode::Leaf(l) => &l.0,
         }
     }
+    /// Mutable access to the value of the leaf containing `point`.
     pub fn get_at_point_mut(&mut self, point: Vec2) -> &mut T {
         let i = self.leaf_index_for_point(point);
         match &mut self.nodes[i] {
@@ -182,50 +183,172 @@ impl<T> Bsp<T> {
             BspNode::Leaf(l) => &mut l.0,
         }
     }
-    pub fn split_at_point(&mut self, point: Vec2, normal: Vec2, new_val: T)
+    /// Splits the leaf containing `point` along the line through `point` with
+    /// unit normal `normal`. The old value stays on the "le" side and
+    /// `new_val` goes on the "gt" side.
+    ///
+    /// Returns the key of the split leaf, which is now an inode.
+    pub fn split_at_point(&mut self, point: Vec2, normal: Vec2, new_val: T) -> BspKey
     where
         T: Clone,
     {
         debug_assert!(normal.is_normalized());
 
         let index = self.leaf_index_for_point(point);
+        let plane = Plane::through(point, normal);
 
-        let plane = Plane {
-            normal,
-            distance: point.dot(normal),
-        };
+        let le = self.nodes.insert(self.nodes[index].clone());
+        let gt = self.nodes.insert(BspNode::Leaf(BspLeaf(new_val)));
+        self.parents.insert(le, index);
+        self.parents.insert(gt, index);
+        self.nodes[index] = BspNode::Inode(BspInode { plane, le, gt });
 
-        self.nodes[index] = BspNode::Inode(BspInode {
-            plane,
-            le: self.nodes.insert(self.nodes[index].clone()),
-            gt: self.nodes.insert(BspNode::Leaf(BspLeaf(new_val))),
-        });
-    }
-    pub fn unsplit_at_point(&mut self, point: Vec2) where T: Clone {
-        // horrific method for getting parent w/o parent pointer
-        let mut parent = self.root_key();
-        let mut leaf = self.root_key();
-        self.visit_leaf_ancestors(point, |key, _| {
-            parent = leaf;
-            leaf = key;
-        });
-
-        if leaf == self.root_key() {
-            return;
+        index
+    }
+    /// Like [`Bsp::split_at_point`], but only if the split leaves both sides
+    /// of `region`, the region of the leaf containing `point`, within
+    /// `constraints`. Otherwise the tree is left alone.
+    pub fn try_split_at_point(
+        &mut self,
+        point: Vec2,
+        normal: Vec2,
+        new_val: T,
+        region: &Polygon,
+        constraints: &SplitConstraints,
+    ) -> Result<BspKey, SplitRejected>
+    where
+        T: Clone,
+    {
+        constraints.check(region, &Plane::through(point, normal))?;
+        Ok(self.split_at_point(point, normal, new_val))
+    }
+    /// Removes the leaf containing `point`, letting its sibling take over
+    /// their parent's region. Does nothing if the root is a leaf.
+    ///
+    /// Returns the key of that parent, which now holds what the sibling did.
+    pub fn unsplit_at_point(&mut self, point: Vec2) -> Option<BspKey> {
+        self.unsplit(self.leaf_index_for_point(point))
+    }
+    /// Removes `key` and everything under it, letting its sibling take over
+    /// their parent's region. Does nothing to the root, or to keys that
+    /// aren't in the tree.
+    ///
+    /// Returns the key of that parent, which now holds what the sibling did.
+    /// The sibling's key goes away; its children keep theirs.
+    pub fn unsplit(&mut self, key: BspKey) -> Option<BspKey> {
+        let parent = self.parent(key)?;
+        let sibling = self.sibling(key)?;
+
+        self.remove_subtree(key);
+        self.parents.remove(sibling);
+        self.nodes[parent] = self.nodes.remove(sibling)?;
+
+        if let BspNode::Inode(BspInode { le, gt, .. }) = self.nodes[parent] {
+            self.parents.insert(le, parent);
+            self.parents.insert(gt, parent);
         }
 
-        self.nodes.remove(leaf);
+        Some(parent)
+    }
+    /// Turns the inode at `key` back into a leaf, dropping everything under
+    /// it. Splits leave the old value on their "le" side, so the leaf gets
+    /// the value found by going "le" all the way down: what `key` held
+    /// before it was split, unless that's been changed since.
+    ///
+    /// Returns false, and does nothing, if `key` isn't an inode.
+    pub fn collapse(&mut self, key: BspKey) -> bool {
+        let (le, gt) = match self.nodes.get(key) {
+            Some(BspNode::Inode(inode)) => (inode.le, inode.gt),
+            _ => return false,
+        };
 
-        let sibling = match &self.nodes[parent] {
-            BspNode::Leaf(_) => unreachable!(),
-            BspNode::Inode(BspInode { le, gt, .. }) => {
-                if *le == leaf { *gt } else { *le }
-            }
+        let mut first = le;
+        while let Some(BspNode::Inode(inode)) = self.nodes.get(first) {
+            first = inode.le;
+        }
+        // taken out before the rest goes, so the value survives
+        let leaf = match self.nodes.remove(first) {
+            Some(BspNode::Leaf(leaf)) => leaf,
+            _ => return false,
         };
+        self.parents.remove(first);
+        self.remove_subtree(le);
+        self.remove_subtree(gt);
+        self.nodes[key] = BspNode::Leaf(leaf);
 
-        self.nodes[parent] = self.nodes.remove(sibling).unwrap();
-        
+        true
     }
+    /// Removes what can't be seen below `start`, whose region is `region`:
+    /// splits whose plane misses their region, leaving one side empty, and
+    /// whole subtrees covering less than `min_area`. Either way the other
+    /// side takes over, as with [`Bsp::unsplit`].
+    ///
+    /// With a `min_area` of 0 this leaves the picture exactly as it was.
+    /// Anything above that lets the other side's value spread over what was
+    /// removed, so it should stay well below a pixel.
+    ///
+    /// Returns how many nodes were removed.
+    pub fn prune(&mut self, start: BspKey, region: Polygon, min_area: f32) -> usize {
+        let before = self.len();
+        let hidden = |region: &Polygon| region.vertices.is_empty() || region.area() < min_area;
+
+        let mut stack = vec![(start, region)];
+        while let Some((key, region)) = stack.pop() {
+            let inode = match self.nodes.get(key) {
+                Some(BspNode::Inode(inode)) => inode,
+                _ => continue,
+            };
+            let (le, gt) = (inode.le, inode.gt);
+            let le_region = region.clip_against_plane(&inode.plane, true);
+            let gt_region = region.clip_against_plane(&inode.plane, false);
+
+            let doomed = if hidden(&le_region) {
+                le
+            } else if hidden(&gt_region) {
+                gt
+            } else {
+                stack.push((le, le_region));
+                stack.push((gt, gt_region));
+                continue;
+            };
+            // `key` now holds the other side, which may need pruning too
+            self.unsplit(doomed);
+            stack.push((key, region));
+        }
+
+        before - self.len()
+    }
+    /// The region of `key`: `bounds` cut down by every plane above it.
+    /// Empty if `key` isn't in the tree.
+    pub fn region(&self, key: BspKey, bounds: Polygon) -> Polygon {
+        if !self.nodes.contains_key(key) {
+            return Polygon { vertices: vec![] };
+        }
+
+        let path: Vec<BspKey> = std::iter::once(key).chain(self.ancestors(key)).collect();
+        path.windows(2).rev().fold(bounds, |region, pair| {
+            let (child, parent) = (pair[0], pair[1]);
+            match &self.nodes[parent] {
+                BspNode::Inode(inode) => region.clip_against_plane(&inode.plane, inode.le == child),
+                BspNode::Leaf(_) => region,
+            }
+        })
+    }
+    fn remove_subtree(&mut self, key: BspKey) {
+        let mut stack = vec![key];
+        while let Some(key) = stack.pop() {
+            self.parents.remove(key);
+            if let Some(BspNode::Inode(inode)) = self.nodes.remove(key) {
+                stack.push(inode.le);
+                stack.push(inode.gt);
+            }
+        }
+    }
+    /// Calls `cb` with every leaf under `start` along with its region, which
+    /// is `clip` cut down by the planes on the way. Pass the root and the
+    /// canvas bounds to visit the whole picture.
+    ///
+    /// Regions can come out empty when a plane misses its parent's region.
     pub fn visit_leaf_polygons<F>(&self, start: BspKey, clip: Polygon, cb: &mut F)
     where
         F: FnMut(&BspLeaf<T>, &Polygon),
@@ -243,23 +366,329 @@ impl<T> Bsp<T> {
             }
         }
     }
+
+    /// Every leaf with its region inside `bounds`, in drawing order.
+    pub fn leaf_polygons(&self, bounds: Polygon) -> Vec<(BspKey, Polygon)> {
+        let mut out = Vec::new();
+        self.visit_keyed_leaf_polygons(self.root, bounds, &mut out);
+        out
+    }
+    fn visit_keyed_leaf_polygons(
+        &self,
+        key: BspKey,
+        clip: Polygon,
+        out: &mut Vec<(BspKey, Polygon)>,
+    ) {
+        match &self.nodes[key] {
+            BspNode::Inode(inode) => {
+                let clipped_le = clip.clip_against_plane(&inode.plane, true);
+                let clipped_gt = clip.clip_against_plane(&inode.plane, false);
+
+                self.visit_keyed_leaf_polygons(inode.le, clipped_le, out);
+                self.visit_keyed_leaf_polygons(inode.gt, clipped_gt, out);
+            }
+            BspNode::Leaf(_) => out.push((key, clip)),
+        }
+    }
+}
+
+// deserialized trees come from files we don't trust, so everything goes
+// through here to be checked before it can become a Bsp.
+#[derive(Deserialize)]
+struct BspRepr<T> {
+    nodes: SlotMap<BspKey, BspNode<T>>,
+    root: BspKey,
+}
+impl<T> TryFrom<BspRepr<T>> for Bsp<T> {
+    type Error = InvalidTree;
+
+    fn try_from(BspRepr { nodes, root }: BspRepr<T>) -> Result<Self, InvalidTree> {
+        if !nodes.contains_key(root) {
+            return Err(InvalidTree("root node is missing"));
+        }
+
+        // every node must be reachable from the root exactly once,
+        // otherwise traversal would loop forever or alias subtrees
+        let mut seen = SecondaryMap::with_capacity(nodes.len());
+        let mut parents = SecondaryMap::with_capacity(nodes.len());
+        let mut stack = vec![root];
+        while let Some(key) = stack.pop() {
+            let node = nodes.get(key).ok_or(InvalidTree("node has a dangling child"))?;
+            if seen.insert(key, ()).is_some() {
+                return Err(InvalidTree("node is reachable more than once"));
+            }
+
+            if let BspNode::Inode(inode) = node {
+                let plane = &inode.plane;
+                if !plane.normal.is_finite()
+                    || !plane.distance.is_finite()
+                    || plane.normal.length_squared() == 0.0
+                {
+                    return Err(InvalidTree("plane is degenerate"));
+                }
+                stack.push(inode.le);
+                stack.push(inode.gt);
+                parents.insert(inode.le, key);
+                parents.insert(inode.gt, key);
+            }
+        }
+        if seen.len() != nodes.len() {
+            return Err(InvalidTree("tree contains unreachable nodes"));
+        }
+
+        Ok(Bsp {
+            nodes,
+            root,
+            parents,
+        })
+    }
+}
+
+/// Why a deserialized tree was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTree(&'static str);
+impl std::fmt::Display for InvalidTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid BSP tree: {}", self.0)
+    }
+}
+impl std::error::Error for InvalidTree {}
+
+/// Limits on what a split may leave on either side of it. Whatever the
+/// limits, both sides have to enclose some area; the default asks for nothing
+/// more.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitConstraints {
+    /// The smallest area either side may have, in the region's units.
+    pub min_area: f32,
+    /// The smallest [`Polygon::aspect_ratio`] either side may have, from 0
+    /// (anything goes) to 1 (squares only).
+    pub min_aspect_ratio: f32,
+}
+impl SplitConstraints {
+    /// Whether cutting `region` along `plane` leaves both sides within the
+    /// limits, and which limit it breaks if not.
+    pub fn check(&self, region: &Polygon, plane: &Plane) -> Result<(), SplitRejected> {
+        for side in [true, false] {
+            let part = region.clip_against_plane(plane, side);
+            if part.vertices.is_empty() {
+                return Err(SplitRejected::Misses);
+            }
+            if part.area() < self.min_area {
+                return Err(SplitRejected::TooSmall);
+            }
+            if part.aspect_ratio() < self.min_aspect_ratio {
+                return Err(SplitRejected::TooThin);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a split was refused by [`SplitConstraints`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitRejected {
+    /// The plane doesn't divide the region, or only shaves off a sliver.
+    Misses,
+    /// One side would be smaller than the minimum area.
+    TooSmall,
+    /// One side would be thinner than the minimum aspect ratio.
+    TooThin,
+}
+impl std::fmt::Display for SplitRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SplitRejected::Misses => "split doesn't divide the region",
+            SplitRejected::TooSmall => "split leaves a side below the minimum area",
+            SplitRejected::TooThin => "split leaves a side below the minimum aspect ratio",
+        })
+    }
 }
+impl std::error::Error for SplitRejected {}
 
-new_key_type! { pub struct BspKey; }
+new_key_type! {
+    /// Names a node in a [`Bsp`].
+    pub struct BspKey;
+}
 
-#[derive(Debug, Clone)]
+/// An interior node: a plane and what's on either side of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BspInode {
+    /// The line this node splits its region along.
     pub plane: Plane,
 
+    /// The child covering the side with `distance_to_point <= 0`.
     pub le: BspKey,
+    /// The child covering the side with `distance_to_point > 0`.
     pub gt: BspKey,
 }
 
-#[derive(Debug, Clone)]
+/// A leaf, holding the value for its whole region.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BspLeaf<T>(pub T);
 
-#[derive(Debug, Clone)]
+/// A node of a [`Bsp`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BspNode<T> {
+    /// A split.
     Inode(BspInode),
+    /// A region with a value.
     Leaf(BspLeaf<T>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_square() -> Polygon {
+        Polygon::new_rect(Vec2::ZERO, Vec2::ONE)
+    }
+
+    // a vertical cut at x = `x`
+    fn cut_at(x: f32) -> Plane {
+        Plane::through(Vec2::new(x, 0.5), Vec2::X)
+    }
+
+    #[test]
+    fn constraints_check() {
+        let square = unit_square();
+        let constraints = SplitConstraints {
+            min_area: 0.2,
+            min_aspect_ratio: 0.3,
+        };
+        assert_eq!(constraints.check(&square, &cut_at(0.5)), Ok(()));
+        assert_eq!(constraints.check(&square, &cut_at(0.1)), Err(SplitRejected::TooSmall));
+        assert_eq!(constraints.check(&square, &cut_at(0.75)), Err(SplitRejected::TooThin));
+        assert_eq!(constraints.check(&square, &cut_at(2.0)), Err(SplitRejected::Misses));
+        assert_eq!(constraints.check(&square, &cut_at(-1.0)), Err(SplitRejected::Misses));
+
+        // the default only asks for both sides to be there
+        let anything = SplitConstraints::default();
+        assert_eq!(anything.check(&square, &cut_at(0.01)), Ok(()));
+        assert_eq!(anything.check(&square, &cut_at(1.5)), Err(SplitRejected::Misses));
+    }
+
+    #[test]
+    fn rejected_splits_leave_the_tree_alone() {
+        let square = unit_square();
+        let constraints = SplitConstraints {
+            min_area: 0.2,
+            min_aspect_ratio: 0.3,
+        };
+        let mut bsp = Bsp::new(0);
+
+        for (point, rejected) in [
+            (Vec2::new(0.1, 0.5), SplitRejected::TooSmall),
+            (Vec2::new(0.75, 0.5), SplitRejected::TooThin),
+        ] {
+            let result = bsp.try_split_at_point(point, Vec2::X, 1, &square, &constraints);
+            assert_eq!(result, Err(rejected));
+            assert_eq!(bsp.len(), 1);
+        }
+        // a corner of the square, cut along a line that only touches it
+        let corner = Vec2::ZERO;
+        let normal = Vec2::new(1.0, 1.0).normalize();
+        let result = bsp.try_split_at_point(corner, normal, 1, &square, &constraints);
+        assert_eq!(result, Err(SplitRejected::Misses));
+        assert_eq!(bsp.len(), 1);
+
+        let root = bsp.root_key();
+        let split = bsp.try_split_at_point(Vec2::splat(0.5), Vec2::X, 1, &square, &constraints);
+        assert_eq!(split, Ok(root));
+        assert_eq!(bsp.len(), 3);
+        assert_eq!(*bsp.get_at_point(Vec2::new(0.25, 0.5)), 0);
+        assert_eq!(*bsp.get_at_point(Vec2::new(0.75, 0.5)), 1);
+    }
+
+    #[test]
+    fn pruning_nothing_away_keeps_the_picture() {
+        use rand::{Rng, SeedableRng};
+
+        let square = unit_square();
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(3);
+        let mut bsp = Bsp::new(0);
+        for value in 1..200 {
+            let point = Vec2::new(rng.gen(), rng.gen());
+            let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+            let key = bsp.split_at_point(point, Vec2::new(angle.cos(), angle.sin()), value);
+            // and now and then slide it off somewhere it may miss, to leave
+            // something to prune
+            if value % 5 == 0 {
+                bsp.plane_mut(key).unwrap().distance += rng.gen_range(-1.0..1.0);
+            }
+        }
+
+        let mut pruned = bsp.clone();
+        let root = pruned.root_key();
+        assert!(pruned.prune(root, square.clone(), 0.0) > 0);
+        assert!(pruned.len() < bsp.len());
+
+        let area = |bsp: &Bsp<i32>| -> f32 {
+            bsp.leaf_polygons(square.clone()).iter().map(|(_, p)| p.area()).sum()
+        };
+        assert!((area(&pruned) - area(&bsp)).abs() < 1e-4);
+
+        let samples = 100;
+        for y in 0..samples {
+            for x in 0..samples {
+                let point = (Vec2::new(x as f32, y as f32) + 0.5) / samples as f32;
+                assert_eq!(pruned.get_at_point(point), bsp.get_at_point(point), "at {}", point);
+            }
+        }
+    }
+
+    // every inode is its children's parent, the root has none, and nothing
+    // else is left over
+    fn check_parents<T>(bsp: &Bsp<T>) {
+        assert_eq!(bsp.parent(bsp.root_key()), None);
+        for (key, node) in bsp.iter() {
+            if let BspNode::Inode(inode) = node {
+                assert_eq!(bsp.parent(inode.le), Some(key));
+                assert_eq!(bsp.parent(inode.gt), Some(key));
+            }
+        }
+        assert_eq!(bsp.parents.len(), bsp.len() - 1);
+    }
+
+    #[test]
+    fn parents_survive_every_edit() {
+        use rand::{Rng, SeedableRng};
+
+        let square = unit_square();
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(5);
+        let mut bsp = Bsp::new(0);
+        for value in 1..500 {
+            let point = Vec2::new(rng.gen(), rng.gen());
+            match rng.gen_range(0..10) {
+                0 => {
+                    bsp.unsplit_at_point(point);
+                }
+                1 => {
+                    let inodes: Vec<BspKey> = bsp
+                        .iter()
+                        .filter(|(_, node)| matches!(node, BspNode::Inode(_)))
+                        .map(|(key, _)| key)
+                        .collect();
+                    if !inodes.is_empty() {
+                        bsp.collapse(inodes[rng.gen_range(0..inodes.len())]);
+                    }
+                }
+                2 => {
+                    let root = bsp.root_key();
+                    bsp.prune(root, square.clone(), 0.001);
+                }
+                3 => {
+                    let json = serde_json::to_string(&bsp).unwrap();
+                    bsp = serde_json::from_str(&json).unwrap();
+                }
+                _ => {
+                    let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+                    bsp.split_at_point(point, Vec2::new(angle.cos(), angle.sin()), value);
+                }
+            }
+            check_parents(&bsp);
+        }
+        assert!(bsp.len() > 1);
+    }
+}
@@ -0,0 +1,377 @@
+//! Keeping every node's region around between edits.
+
+use std::collections::{HashMap, HashSet};
+
+use slotmap::SecondaryMap;
+
+use crate::bsp::{Bsp, BspKey, BspNode};
+use crate::geom::{Plane, Polygon, EPSILON};
+
+/// The region of every node in a tree, and which leaves share an edge, kept
+/// in sync with it.
+///
+/// Clipping the whole tree is linear in its size, which adds up when it's
+/// done every frame. Edits only ever change the regions below one node, so
+/// after an edit, tell the cache which node that was with
+/// [`update_subtree`](RegionCache::update_subtree) and only that part gets
+/// clipped again. Anything that replaces the tree wholesale (undo, loading)
+/// needs a [`rebuild`](RegionCache::rebuild).
+///
+/// Two leaves share an edge only along the plane of the inode where their
+/// paths from the root part ways, so that's the only place neighbours are
+/// looked for. An edit only changes which leaves are neighbours inside the
+/// edited subtree, and along its outline, where the neighbours outside it
+/// were already neighbours of something in it before.
+#[derive(Clone)]
+pub struct RegionCache {
+    bounds: Polygon,
+    nodes: SecondaryMap<BspKey, CachedNode>,
+}
+
+// a copy of the tree's shape as of the last update, so we know what to throw
+// away even after the tree has moved on
+#[derive(Clone)]
+struct CachedNode {
+    region: Polygon,
+    children: Option<(BspKey, BspKey)>,
+    // leaves only: the leaves sharing an edge with this one and how long that
+    // edge is, sorted by key so the order doesn't depend on how we got here
+    neighbours: Vec<(BspKey, f32)>,
+}
+
+// leaves running along a line, and the stretch of it each covers
+type Spans = Vec<(BspKey, (f32, f32))>;
+
+impl RegionCache {
+    /// Clips all of `bsp` against `bounds`.
+    pub fn new<T>(bsp: &Bsp<T>, bounds: Polygon) -> RegionCache {
+        let mut cache = RegionCache {
+            bounds,
+            nodes: SecondaryMap::new(),
+        };
+        cache.rebuild(bsp);
+        cache
+    }
+
+    /// The region the root covers.
+    pub fn bounds(&self) -> &Polygon {
+        &self.bounds
+    }
+
+    /// Throws everything away and clips the whole tree again.
+    pub fn rebuild<T>(&mut self, bsp: &Bsp<T>) {
+        self.nodes.clear();
+        self.fill(bsp, bsp.root_key(), self.bounds.clone());
+        self.link_within(bsp, bsp.root_key());
+    }
+
+    /// Changes the root region, which changes everything.
+    pub fn set_bounds<T>(&mut self, bsp: &Bsp<T>, bounds: Polygon) {
+        self.bounds = bounds;
+        self.rebuild(bsp);
+    }
+
+    /// Catches up with an edit that changed the tree below `key`, but not the
+    /// region of `key` itself: splitting a leaf, unsplitting one of a node's
+    /// children, moving a node's plane.
+    pub fn update_subtree<T>(&mut self, bsp: &Bsp<T>, key: BspKey) {
+        let region = match self.nodes.get(key) {
+            Some(node) => node.region.clone(),
+            // not something we've seen; nothing to go on but the whole tree
+            None => return self.rebuild(bsp),
+        };
+
+        // forget the old subtree, which may not be in the tree anymore
+        // (a secondary map would be as big as the whole tree)
+        let mut old_leaves = HashMap::new();
+        let mut stack = vec![key];
+        while let Some(old) = stack.pop() {
+            let removed = if old == key {
+                self.nodes.get(old).cloned()
+            } else {
+                self.nodes.remove(old)
+            };
+            match removed {
+                Some(CachedNode {
+                    children: Some((le, gt)),
+                    ..
+                }) => {
+                    stack.push(le);
+                    stack.push(gt);
+                }
+                Some(node) => {
+                    old_leaves.insert(old, node.neighbours);
+                }
+                None => {}
+            }
+        }
+
+        // and everything outside it that used to border it
+        let mut outside: Vec<BspKey> = old_leaves
+            .values()
+            .flatten()
+            .map(|&(neighbour, _)| neighbour)
+            .filter(|neighbour| !old_leaves.contains_key(neighbour))
+            .collect();
+        outside.sort();
+        outside.dedup();
+        for &neighbour in &outside {
+            if let Some(node) = self.nodes.get_mut(neighbour) {
+                node.neighbours
+                    .retain(|(key, _)| !old_leaves.contains_key(key));
+            }
+        }
+
+        self.fill(bsp, key, region);
+        self.link_within(bsp, key);
+        self.link_across(bsp, key, &outside);
+    }
+
+    /// The region of `key`, if it's in the tree.
+    pub fn region(&self, key: BspKey) -> Option<&Polygon> {
+        self.nodes.get(key).map(|node| &node.region)
+    }
+
+    /// The leaves sharing an edge with the leaf `key`, each with the length
+    /// of that edge. Leaves that only touch at a corner aren't included.
+    /// Empty for inodes and keys that aren't in the tree.
+    pub fn neighbours(&self, key: BspKey) -> &[(BspKey, f32)] {
+        self.nodes
+            .get(key)
+            .map_or(&[], |node| node.neighbours.as_slice())
+    }
+
+    /// Every pair of leaves sharing an edge, once each, with the length of
+    /// that edge.
+    pub fn adjacencies(&self) -> impl Iterator<Item = (BspKey, BspKey, f32)> + '_ {
+        self.nodes.iter().flat_map(|(a, node)| {
+            node.neighbours
+                .iter()
+                .filter(move |&&(b, _)| a < b)
+                .map(move |&(b, length)| (a, b, length))
+        })
+    }
+
+    /// Every leaf and its region, in no particular order.
+    pub fn leaves(&self) -> impl Iterator<Item = (BspKey, &Polygon)> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.children.is_none())
+            .map(|(key, node)| (key, &node.region))
+    }
+
+    fn fill<T>(&mut self, bsp: &Bsp<T>, key: BspKey, region: Polygon) {
+        let mut stack = vec![(key, region)];
+
+        while let Some((key, region)) = stack.pop() {
+            let children = match bsp.get(key) {
+                Some(BspNode::Inode(inode)) => {
+                    stack.push((inode.le, region.clip_against_plane(&inode.plane, true)));
+                    stack.push((inode.gt, region.clip_against_plane(&inode.plane, false)));
+                    Some((inode.le, inode.gt))
+                }
+                Some(BspNode::Leaf(_)) => None,
+                None => continue,
+            };
+
+            self.nodes.insert(
+                key,
+                CachedNode {
+                    region,
+                    children,
+                    neighbours: Vec::new(),
+                },
+            );
+        }
+    }
+
+    // finds the neighbours that are both under `start`, which has to be
+    // filled in already, at the split where they part ways
+    fn link_within<T>(&mut self, bsp: &Bsp<T>, start: BspKey) {
+        let mut stack = vec![start];
+        while let Some(key) = stack.pop() {
+            let inode = match bsp.get(key) {
+                Some(BspNode::Inode(inode)) => inode,
+                _ => continue,
+            };
+            stack.push(inode.le);
+            stack.push(inode.gt);
+
+            let le = self.spans(inode.le, &inode.plane);
+            let gt = self.spans(inode.gt, &inode.plane);
+            self.link_spans(&le, &gt);
+        }
+    }
+
+    // finds the neighbours of what's under `start` among `outside`, the leaves
+    // that bordered it before an edit
+    fn link_across<T>(&mut self, bsp: &Bsp<T>, start: BspKey, outside: &[BspKey]) {
+        let above: HashSet<BspKey> = bsp.ancestors(start).collect();
+
+        // grouped by the split between them and `start`, of which there are
+        // only ever a few
+        let mut by_split: Vec<(BspKey, Vec<BspKey>)> = Vec::new();
+        for &neighbour in outside {
+            let split = match bsp.ancestors(neighbour).find(|key| above.contains(key)) {
+                Some(split) => split,
+                None => continue,
+            };
+            match by_split.iter_mut().find(|(key, _)| *key == split) {
+                Some((_, neighbours)) => neighbours.push(neighbour),
+                None => by_split.push((split, vec![neighbour])),
+            }
+        }
+
+        for (split, neighbours) in by_split {
+            let plane = match bsp.get(split) {
+                Some(BspNode::Inode(inode)) => &inode.plane,
+                _ => continue,
+            };
+            let mut theirs: Spans = neighbours
+                .into_iter()
+                .filter_map(|key| Some((key, self.nodes.get(key)?.region.span_along(plane)?)))
+                .collect();
+            theirs.sort_by(|a, b| a.1 .0.total_cmp(&b.1 .0));
+
+            let ours = self.spans(start, plane);
+            self.link_spans(&ours, &theirs);
+        }
+    }
+
+    // the leaves under `start` running along `plane`, which has to be on one
+    // side of them, in order along it
+    fn spans(&self, start: BspKey, plane: &Plane) -> Spans {
+        let mut spans = Vec::new();
+        let mut stack = vec![start];
+        while let Some(key) = stack.pop() {
+            let node = match self.nodes.get(key) {
+                Some(node) => node,
+                None => continue,
+            };
+            let span = match node.region.span_along(plane) {
+                Some(span) => span,
+                // nothing below can reach the line either
+                None => continue,
+            };
+            match node.children {
+                Some((le, gt)) => stack.extend([le, gt]),
+                None => spans.push((key, span)),
+            }
+        }
+        spans.sort_by(|a, b| a.1 .0.total_cmp(&b.1 .0));
+        spans
+    }
+
+    // links the leaves from either side of a line wherever their stretches
+    // of it overlap; each side's stretches follow one another without gaps
+    fn link_spans(&mut self, a: &[(BspKey, (f32, f32))], b: &[(BspKey, (f32, f32))]) {
+        let (mut i, mut j) = (0, 0);
+        while let (Some(&(a_key, (a_lo, a_hi))), Some(&(b_key, (b_lo, b_hi)))) =
+            (a.get(i), b.get(j))
+        {
+            let shared = a_hi.min(b_hi) - a_lo.max(b_lo);
+            if shared > EPSILON {
+                self.link(a_key, b_key, shared);
+            }
+            if a_hi < b_hi {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+
+    fn link(&mut self, a: BspKey, b: BspKey, length: f32) {
+        for (from, to) in [(a, b), (b, a)] {
+            if let Some(node) = self.nodes.get_mut(from) {
+                let neighbours = &mut node.neighbours;
+                match neighbours.binary_search_by_key(&to, |&(key, _)| key) {
+                    Ok(i) => neighbours[i].1 = length,
+                    Err(i) => neighbours.insert(i, (to, length)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn sorted_adjacencies(cache: &RegionCache) -> Vec<(BspKey, BspKey, f32)> {
+        let mut pairs: Vec<_> = cache.adjacencies().collect();
+        pairs.sort_by_key(|&(a, b, _)| (a, b));
+        pairs
+    }
+
+    // the same leaves bordering each other along edges about as long
+    fn assert_same_adjacencies(cache: &RegionCache, fresh: &RegionCache, step: usize) {
+        let (ours, theirs) = (sorted_adjacencies(cache), sorted_adjacencies(fresh));
+        let keys = |pairs: &[(BspKey, BspKey, f32)]| -> Vec<_> {
+            pairs.iter().map(|&(a, b, _)| (a, b)).collect()
+        };
+        assert_eq!(keys(&ours), keys(&theirs), "step {}", step);
+        for ((a, b, ours), (_, _, theirs)) in ours.iter().zip(&theirs) {
+            assert!(
+                (ours - theirs).abs() < 1e-4,
+                "step {}: {:?}-{:?} is {} long, not {}",
+                step,
+                a,
+                b,
+                ours,
+                theirs
+            );
+        }
+    }
+
+    #[test]
+    fn updates_match_a_rebuild() {
+        let bounds = Polygon::new_rect(Vec2::ZERO, Vec2::new(2.0, 1.0));
+        for seed in 0..5 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let mut bsp = Bsp::new(());
+            let mut cache = RegionCache::new(&bsp, bounds.clone());
+
+            for step in 0..300 {
+                let leaves: Vec<(BspKey, Vec2)> = cache
+                    .leaves()
+                    .filter_map(|(key, region)| Some((key, region.centroid()?)))
+                    .collect();
+                let inodes: Vec<BspKey> = bsp
+                    .iter()
+                    .filter(|(_, node)| matches!(node, BspNode::Inode(_)))
+                    .map(|(key, _)| key)
+                    .collect();
+
+                // mostly splits, so the tree grows
+                let edited = match rng.gen_range(0..10) {
+                    0 | 1 if !leaves.is_empty() => {
+                        let (leaf, _) = leaves[rng.gen_range(0..leaves.len())];
+                        bsp.unsplit(leaf)
+                    }
+                    2 if !inodes.is_empty() => {
+                        let inode = inodes[rng.gen_range(0..inodes.len())];
+                        bsp.collapse(inode).then_some(inode)
+                    }
+                    _ if !leaves.is_empty() => {
+                        let (_, centroid) = leaves[rng.gen_range(0..leaves.len())];
+                        let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+                        let normal = Vec2::new(angle.cos(), angle.sin());
+                        Some(bsp.split_at_point(centroid, normal, ()))
+                    }
+                    _ => None,
+                };
+                if let Some(key) = edited {
+                    cache.update_subtree(&bsp, key);
+                }
+
+                let fresh = RegionCache::new(&bsp, bounds.clone());
+                assert_same_adjacencies(&cache, &fresh, step);
+            }
+        }
+    }
+}
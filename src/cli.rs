@@ -0,0 +1,473 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+use palette::Oklab;
+use rand::SeedableRng;
+
+use wrong_track::{bsp, export, raster, Join, Polygon, RegionCache, SplitConstraints, Style};
+
+use crate::{coloring, generate, palettes, project};
+
+#[derive(Parser)]
+#[command(name = "wrong-track", version, about = "bsp-based graphics toy")]
+#[command(after_help = "Without a subcommand, opens the GUI.")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Build a picture from random splits and write it to disk, headless
+    Generate(GenerateArgs),
+}
+
+#[derive(Args)]
+pub struct GenerateArgs {
+    /// How many random splits to make (SPLIT X100 is 100 of these)
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    splits: usize,
+
+    /// RNG seed; a random one is picked and printed if not given
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[arg(long, default_value_t = 0.5, value_parser = parse_unit)]
+    normal_randomness: f32,
+
+    #[arg(long, default_value_t = 0.5, value_parser = parse_unit)]
+    color_randomness: f32,
+
+    #[arg(long, default_value_t = 3, value_parser = parse_num_color_samples)]
+    num_color_samples: usize,
+
+    /// What new colors start from: samples (around the split) or neighbours
+    /// (of the split region, weighted by shared edge)
+    #[arg(long, default_value = "samples", value_parser = parse_color_source)]
+    color_source: generate::ColorSource,
+
+    /// After splitting, relax every color towards its neighbours this many
+    /// times, like the GUI's SMOOTH
+    #[arg(long, default_value_t = 0)]
+    smooth: usize,
+
+    /// How far each smoothing pass moves colors, from 0 to 1
+    #[arg(long, default_value_t = 0.5, value_parser = parse_unit)]
+    smooth_strength: f32,
+
+    /// After splitting (and smoothing), color every region from --palette so
+    /// that no neighbours match, like the GUI's RECOLOR. Projects written
+    /// keep new regions apart from their neighbours too
+    #[arg(long)]
+    recolor: bool,
+
+    /// What --recolor and --palette-mode pick from: primaries, atlas,
+    /// pastel, earth or greys, or a .gpl, .ase or list of hex colors to
+    /// import
+    #[arg(long, default_value = "primaries", value_parser = parse_palette)]
+    palette: palettes::Palette,
+
+    /// Whether new colors are kept to --palette: off, pick (at random, by
+    /// weight) or snap (to the nearest palette color)
+    #[arg(long, default_value = "off", value_parser = parse_palette_mode)]
+    palette_mode: generate::PaletteMode,
+
+    /// Picks between the ways --recolor can color the same picture
+    #[arg(long, default_value_t = 0)]
+    recolor_seed: u64,
+
+    /// Give every new region this color, as #rrggbb
+    #[arg(long, value_parser = parse_hex_color)]
+    override_color: Option<[u8; 3]>,
+
+    /// Size of the document, and of PNG output, as WIDTHxHEIGHT
+    #[arg(long, default_value = "2048x2048", value_parser = parse_size)]
+    size: [u32; 2],
+
+    /// Outline width around each region, in pixels
+    #[arg(long, default_value_t = 0.0, value_parser = parse_length)]
+    outline_width: f32,
+
+    /// Outline color, as #rrggbb
+    #[arg(long, default_value = "#000000", value_parser = parse_hex_color)]
+    outline_color: [u8; 3],
+
+    /// How outlines go around corners: miter, round or bevel
+    #[arg(long, default_value = "miter", value_parser = parse_join)]
+    outline_join: Join,
+
+    /// How far each region is inset, in pixels; the gap between neighbours
+    /// is twice this
+    #[arg(long, default_value_t = 0.0, value_parser = parse_length)]
+    gutter: f32,
+
+    /// What shows through the gaps, as #rrggbb; transparent if not given
+    #[arg(long, value_parser = parse_hex_color)]
+    gutter_color: Option<[u8; 3]>,
+
+    /// Smallest area either side of a split may have, in square pixels;
+    /// splits leaving less are rolled again
+    #[arg(long, default_value_t = 0.0, value_parser = parse_length)]
+    min_area: f32,
+
+    /// Smallest aspect ratio (narrowest width over length) either side of a
+    /// split may have, from 0 to 1; splits leaving thinner sides are rolled
+    /// again
+    #[arg(long, default_value_t = 0.0, value_parser = parse_unit)]
+    min_aspect_ratio: f32,
+
+    /// Prune after every split, dropping splits that don't divide anything
+    #[arg(long)]
+    prune: bool,
+
+    /// With --prune, also drop regions smaller than this, in square pixels
+    #[arg(long, default_value_t = 0.0, value_parser = parse_length)]
+    prune_min_area: f32,
+
+    /// Where to write; the format comes from the extension
+    /// (.svg, .png, or .wrongtrack for a project to open in the GUI)
+    #[arg(short, long = "output", required = true)]
+    outputs: Vec<PathBuf>,
+}
+
+impl GenerateArgs {
+    // lengths on the command line are in pixels, the style's are relative to
+    // the long side
+    fn pixels_per_unit(&self) -> f32 {
+        self.size[0].max(self.size[1]) as f32
+    }
+
+    // square pixels in canvas units squared
+    fn to_area(&self, px: f32) -> f32 {
+        (px / (self.pixels_per_unit() * self.pixels_per_unit())).min(1.0)
+    }
+
+    // what the GUI's split_params would give with the same settings
+    fn split_params(&self) -> generate::SplitParams {
+        generate::SplitParams {
+            normal_randomness: self.normal_randomness,
+            color_randomness: self.color_randomness,
+            num_color_samples: self.num_color_samples,
+            color_source: self.color_source,
+            override_color: self.override_color.map(generate::color_from_srgb8),
+            canvas: wrong_track::canvas_size(self.size[0], self.size[1]),
+            constraints: SplitConstraints {
+                min_area: self.to_area(self.min_area),
+                min_aspect_ratio: self.min_aspect_ratio,
+            },
+            prune: self.prune.then(|| self.to_area(self.prune_min_area)),
+            palette_mode: self.palette_mode,
+            palette: self.palette.weighted(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Svg,
+    Png,
+    Project,
+}
+
+/// Runs a subcommand, returning the process exit code.
+pub fn run(command: Command) -> i32 {
+    let result = match command {
+        Command::Generate(args) => generate(args),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+fn generate(args: GenerateArgs) -> Result<(), String> {
+    // check every output before doing any work
+    let outputs = args
+        .outputs
+        .iter()
+        .map(|path| Ok((path, format_for(path)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let seed = args.seed.unwrap_or_else(|| {
+        let seed = rand::random();
+        eprintln!("seed: {}", seed);
+        seed
+    });
+    let mut rng = generate::Rng::seed_from_u64(seed);
+
+    let params = args.split_params();
+    let pixels_per_unit = args.pixels_per_unit();
+    let style = Style {
+        outline_width: (args.outline_width / pixels_per_unit).min(project::MAX_STYLE_LENGTH),
+        outline_color: srgb8(args.outline_color),
+        outline_join: args.outline_join,
+        gutter: (args.gutter / pixels_per_unit).min(project::MAX_STYLE_LENGTH),
+        gutter_color: args.gutter_color.map(srgb8),
+    };
+
+    let (mut bsp, regions, missed) = random_tree(&params, &mut rng, args.splits);
+    if missed > 0 {
+        eprintln!(
+            "warning: {} of {} splits found no room within the constraints",
+            missed, args.splits
+        );
+    }
+    generate::smooth(&mut bsp, &regions, args.smooth_strength, args.smooth);
+    if args.recolor {
+        let clashes = coloring::recolor(&mut bsp, &regions, &args.palette.oklab(), args.recolor_seed);
+        if clashes > 0 {
+            eprintln!(
+                "warning: {} pairs of neighbours still match after recoloring",
+                clashes
+            );
+        }
+    }
+
+    for (path, format) in outputs {
+        let data = match format {
+            Format::Svg => export::save_svg(&bsp, args.size[0], args.size[1], &style),
+            Format::Png => raster::save_png(&bsp, args.size[0], args.size[1], &style)
+                .map_err(|e| format!("could not make {}: {}", path.display(), e))?,
+            Format::Project => {
+                let oc = egui::epaint::Hsva::from_srgb(args.override_color.unwrap_or_default());
+                project::Project {
+                    settings: project::Settings {
+                        normal_randomness: params.normal_randomness,
+                        color_randomness: params.color_randomness,
+                        num_color_samples: params.num_color_samples,
+                        color_source: params.color_source,
+                        override_color_enabled: args.override_color.is_some(),
+                        override_color: [oc.h, oc.s, oc.v, oc.a],
+                        seed,
+                        rng_position: rng.get_word_pos() as u64,
+                        document_size: args.size,
+                        style: style.clone(),
+                        split_constraints: params.constraints,
+                        auto_prune: args.prune,
+                        prune_min_area: args.to_area(args.prune_min_area),
+                        palette: args.palette.clone(),
+                        palettes: palettes::Palette::builtin(),
+                        palette_mode: args.palette_mode,
+                        recolor_seed: args.recolor_seed,
+                        keep_apart: args.recolor,
+                    },
+                    bsp: bsp.clone(),
+                }
+                .to_bytes()
+            }
+        };
+
+        std::fs::write(path, data)
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+// `splits` random splits of a fresh tree, made the way RND SPLIT makes them,
+// and how many of them found no room
+fn random_tree(
+    params: &generate::SplitParams,
+    rng: &mut generate::Rng,
+    splits: usize,
+) -> (bsp::Bsp<Oklab>, RegionCache, usize) {
+    let mut bsp = bsp::Bsp::new(Oklab::new(1.0, 0.0, 0.0));
+    let mut regions = RegionCache::new(&bsp, Polygon::new_rect(glam::Vec2::ZERO, params.canvas));
+    let mut missed = 0;
+    for _ in 0..splits {
+        match generate::random_split(&mut bsp, &regions, rng, params) {
+            Some(key) => regions.update_subtree(&bsp, key),
+            None => missed += 1,
+        }
+    }
+    (bsp, regions, missed)
+}
+
+fn format_for(path: &Path) -> Result<Format, String> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("svg") => Ok(Format::Svg),
+        Some("png") => Ok(Format::Png),
+        Some(project::EXTENSION) => Ok(Format::Project),
+        _ => Err(format!(
+            "don't know what format to write {} in (use .svg, .png or .{})",
+            path.display(),
+            project::EXTENSION
+        )),
+    }
+}
+
+fn parse_unit(s: &str) -> Result<f32, String> {
+    let x: f32 = s.parse().map_err(|e| format!("{}", e))?;
+    if (0.0..=1.0).contains(&x) {
+        Ok(x)
+    } else {
+        Err("must be between 0 and 1".to_owned())
+    }
+}
+
+fn parse_num_color_samples(s: &str) -> Result<usize, String> {
+    let n: usize = s.parse().map_err(|e| format!("{}", e))?;
+    if (1..=64).contains(&n) {
+        Ok(n)
+    } else {
+        Err("must be between 1 and 64".to_owned())
+    }
+}
+
+fn parse_hex_color(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err("expected a color like #ff8800".to_owned());
+    }
+
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| "expected a color like #ff8800")
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn parse_length(s: &str) -> Result<f32, String> {
+    let x: f32 = s.parse().map_err(|e| format!("{}", e))?;
+    if x.is_finite() && x >= 0.0 {
+        Ok(x)
+    } else {
+        Err("must be a length of at least 0".to_owned())
+    }
+}
+
+fn parse_color_source(s: &str) -> Result<generate::ColorSource, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "samples" => Ok(generate::ColorSource::Samples),
+        "neighbours" | "neighbors" => Ok(generate::ColorSource::Neighbours),
+        _ => Err("expected samples or neighbours".to_owned()),
+    }
+}
+
+fn parse_palette(s: &str) -> Result<palettes::Palette, String> {
+    let builtin = palettes::Palette::builtin()
+        .into_iter()
+        .find(|palette| palette.name.eq_ignore_ascii_case(s.trim()));
+    if let Some(palette) = builtin {
+        return Ok(palette);
+    }
+
+    let bytes = std::fs::read(s).map_err(|e| {
+        format!(
+            "expected primaries, atlas, pastel, earth, greys or a palette file ({})",
+            e
+        )
+    })?;
+    palettes::Palette::import(s, &bytes).map_err(|e| e.to_string())
+}
+
+fn parse_palette_mode(s: &str) -> Result<generate::PaletteMode, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "off" => Ok(generate::PaletteMode::Off),
+        "pick" => Ok(generate::PaletteMode::Pick),
+        "snap" => Ok(generate::PaletteMode::Snap),
+        _ => Err("expected off, pick or snap".to_owned()),
+    }
+}
+
+fn parse_join(s: &str) -> Result<Join, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "miter" => Ok(Join::Miter),
+        "round" => Ok(Join::Round),
+        "bevel" => Ok(Join::Bevel),
+        _ => Err("expected miter, round or bevel".to_owned()),
+    }
+}
+
+fn srgb8([r, g, b]: [u8; 3]) -> palette::Srgb {
+    palette::Srgb::new(r, g, b).into_format()
+}
+
+fn parse_size(s: &str) -> Result<[u32; 2], String> {
+    let (w, h) = s
+        .split_once(['x', 'X'])
+        .ok_or("expected a size like 1920x1080")?;
+    let dim = |d: &str| match d.trim().parse::<u32>() {
+        Ok(d) if (1..=project::MAX_DOCUMENT_SIZE).contains(&d) => Ok(d),
+        _ => Err(format!("bad dimension {:?}", d)),
+    };
+    Ok([dim(w)?, dim(h)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate_args(args: &str) -> GenerateArgs {
+        let cli = "wrong-track generate --output out.svg ".to_owned() + args;
+        match Cli::try_parse_from(cli.split_whitespace()).unwrap().command {
+            Some(Command::Generate(args)) => args,
+            None => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn same_tree_as_the_gui() {
+        let args = generate_args(
+            "-n 80 --seed 11 --size 400x300 --normal-randomness 0.2 --color-randomness 0.7 \
+             --num-color-samples 5 --color-source neighbours --palette atlas --palette-mode snap \
+             --min-area 100 --min-aspect-ratio 0.1 --prune --prune-min-area 4",
+        );
+        let mut rng = generate::Rng::seed_from_u64(11);
+        let (cli, _, _) = random_tree(&args.split_params(), &mut rng, 80);
+
+        // the same settings as the side panel keeps them, areas relative to
+        // the long side
+        let atlas = palettes::Palette::builtin()
+            .into_iter()
+            .find(|palette| palette.name == "Atlas")
+            .unwrap();
+        let params = generate::SplitParams {
+            normal_randomness: 0.2,
+            color_randomness: 0.7,
+            num_color_samples: 5,
+            color_source: generate::ColorSource::Neighbours,
+            override_color: None,
+            palette_mode: generate::PaletteMode::Snap,
+            palette: atlas.weighted(),
+            canvas: wrong_track::canvas_size(400, 300),
+            constraints: SplitConstraints {
+                min_area: 100.0 / (400.0 * 400.0),
+                min_aspect_ratio: 0.1,
+            },
+            prune: Some(4.0 / (400.0 * 400.0)),
+        };
+        let mut rng = generate::Rng::seed_from_u64(11);
+        let mut gui = bsp::Bsp::new(Oklab::new(1.0, 0.0, 0.0));
+        let mut regions =
+            RegionCache::new(&gui, Polygon::new_rect(glam::Vec2::ZERO, params.canvas));
+        for _ in 0..80 {
+            if let Some(key) = generate::random_split(&mut gui, &regions, &mut rng, &params) {
+                regions.update_subtree(&gui, key);
+            }
+        }
+
+        assert!(cli.len() > 1);
+        let json = |bsp: &bsp::Bsp<Oklab>| serde_json::to_string(bsp).unwrap();
+        assert_eq!(json(&cli), json(&gui));
+    }
+
+    #[test]
+    fn format_from_the_extension() {
+        let format = |path: &str| format_for(Path::new(path));
+        assert!(matches!(format("out.svg"), Ok(Format::Svg)));
+        assert!(matches!(format("dir.png/out.PNG"), Ok(Format::Png)));
+        assert!(matches!(format("out.wrongtrack"), Ok(Format::Project)));
+        assert!(format("out.jpg").is_err());
+        assert!(format("out").is_err());
+        assert!(format("svg").is_err());
+    }
+}
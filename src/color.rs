@@ -0,0 +1,197 @@
+//! Getting Oklab colors into sRGB, for screens and files.
+//!
+//! Mixing in Oklab can land outside what sRGB can show. Clipping each
+//! channel on its own shifts the hue and flattens gradients, so
+//! [`to_srgb`] keeps lightness and hue and gives up chroma instead, the
+//! way Oklch gamut mapping does. Everything that draws leaves goes through
+//! it, so the screen and every file agree.
+//!
+//! Colors going the other way, into a tree, go through [`from_srgb`], which
+//! gives the same bits on every platform.
+
+use palette::convert::IntoColorUnclamped;
+use palette::{IntoColor, LinSrgb, Oklab, Srgb};
+
+/// How far outside 0 to 1 a linear sRGB channel can be and still count as
+/// in gamut, to allow for rounding in the conversions.
+const GAMUT_TOLERANCE: f32 = 1e-4;
+/// Halvings in the search for the most chroma that fits; plenty for 8 bits.
+const SEARCH_STEPS: usize = 16;
+
+/// Whether `color` can be shown in sRGB as it is.
+pub fn in_gamut(color: Oklab) -> bool {
+    // the clamped conversion would say yes to anything
+    let rgb: LinSrgb = color.into_color_unclamped();
+    [rgb.red, rgb.green, rgb.blue]
+        .iter()
+        .all(|c| (-GAMUT_TOLERANCE..=1.0 + GAMUT_TOLERANCE).contains(c))
+}
+
+/// `color` in sRGB, with as much of its chroma as fits. Its lightness is
+/// kept to 0 to 1 and its hue stays the same.
+pub fn to_srgb(color: Oklab) -> Srgb {
+    let rgb: Srgb = gamut_map(color).into_color();
+    let unit = |x: f32| x.clamp(0.0, 1.0);
+    Srgb::new(unit(rgb.red), unit(rgb.green), unit(rgb.blue))
+}
+
+/// 8-bit channels for `color`, rounded to the nearest step.
+pub fn to_srgb8(color: Srgb) -> [u8; 3] {
+    let channel = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [
+        channel(color.red),
+        channel(color.green),
+        channel(color.blue),
+    ]
+}
+
+/// `color` in Oklab. The transfer curve and cube roots come from libm rather
+/// than the platform's maths library, which differs between native and wasm
+/// in the last bit, so a seed makes the same colors everywhere.
+pub fn from_srgb(color: Srgb) -> Oklab {
+    let linear = |c: f32| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            libm::powf((c + 0.055) / 1.055, 2.4)
+        }
+    };
+    let (r, g, b) = (linear(color.red), linear(color.green), linear(color.blue));
+
+    // Ottosson's matrices, straight from linear sRGB
+    #[allow(clippy::excessive_precision)]
+    let (l, m, s) = (
+        libm::cbrtf(0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b),
+        libm::cbrtf(0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b),
+        libm::cbrtf(0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b),
+    );
+    #[allow(clippy::excessive_precision)]
+    Oklab::new(
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    )
+}
+
+fn gamut_map(color: Oklab) -> Oklab {
+    if in_gamut(color) {
+        return color;
+    }
+    if !(color.l.is_finite() && color.a.is_finite() && color.b.is_finite()) {
+        return Oklab::new(0.0, 0.0, 0.0);
+    }
+
+    // every grey between black and white fits, so there's always something
+    // to fall back to; scaling a and b together is scaling chroma in Oklch
+    let l = color.l.clamp(0.0, 1.0);
+    let with_chroma = |t: f32| Oklab::new(l, color.a * t, color.b * t);
+    let (mut fits, mut doesnt) = (0.0, 1.0);
+    if in_gamut(with_chroma(1.0)) {
+        return with_chroma(1.0);
+    }
+    for _ in 0..SEARCH_STEPS {
+        let t = (fits + doesnt) * 0.5;
+        if in_gamut(with_chroma(t)) {
+            fits = t;
+        } else {
+            doesnt = t;
+        }
+    }
+    with_chroma(fits)
+}
+
+#[cfg(test)]
+mod tests {
+    use palette::Oklch;
+
+    use super::*;
+
+    fn oklab(color: Srgb) -> Oklab {
+        color.into_color()
+    }
+
+    #[test]
+    fn white_and_black_are_exact() {
+        assert_eq!(to_srgb8(Srgb::new(1.0, 1.0, 1.0)), [255; 3]);
+        assert_eq!(to_srgb8(Srgb::new(0.0, 0.0, 0.0)), [0; 3]);
+        assert_eq!(to_srgb8(to_srgb(oklab(Srgb::new(1.0, 1.0, 1.0)))), [255; 3]);
+        assert_eq!(to_srgb8(Srgb::new(2.0, -1.0, 0.5)), [255, 0, 128]);
+    }
+
+    #[test]
+    fn in_gamut_passes_through() {
+        for rgb in [
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.2, 0.4, 0.6],
+            [0.5, 0.5, 0.5],
+        ] {
+            let color = Srgb::new(rgb[0], rgb[1], rgb[2]);
+            assert!(in_gamut(oklab(color)), "{:?}", rgb);
+            let back = to_srgb(oklab(color));
+            for (a, b) in [
+                (back.red, color.red),
+                (back.green, color.green),
+                (back.blue, color.blue),
+            ] {
+                assert!((a - b).abs() < 1e-3, "{:?} came back as {:?}", rgb, back);
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_gamut_keeps_hue_and_lightness() {
+        // far more chroma than any sRGB color has
+        let color = Oklab::new(0.7, 0.3, 0.2);
+        assert!(!in_gamut(color));
+
+        let mapped: Oklab = to_srgb(color).into_color();
+        assert!(in_gamut(mapped));
+        let (before, after): (Oklch, Oklch) = (color.into_color(), mapped.into_color());
+        assert!(
+            (after.l - before.l).abs() < 1e-2,
+            "{:?} -> {:?}",
+            before,
+            after
+        );
+        let hue_shift = (after.hue - before.hue).to_degrees().abs();
+        assert!(hue_shift < 1.0, "{:?} -> {:?}", before, after);
+        assert!(after.chroma < before.chroma);
+        assert!(after.chroma > 0.05);
+    }
+
+    #[test]
+    fn from_srgb_matches_palette() {
+        for rgb in [
+            [0, 0, 0],
+            [255, 255, 255],
+            [255, 0, 0],
+            [12, 200, 99],
+            [1, 2, 3],
+        ] {
+            let color = Srgb::new(rgb[0], rgb[1], rgb[2]).into_format::<f32>();
+            let (ours, theirs) = (from_srgb(color), oklab(color));
+            for (a, b) in [(ours.l, theirs.l), (ours.a, theirs.a), (ours.b, theirs.b)] {
+                assert!(
+                    (a - b).abs() < 1e-4,
+                    "{:?}: {:?} vs {:?}",
+                    rgb,
+                    ours,
+                    theirs
+                );
+            }
+            assert_eq!(to_srgb8(to_srgb(ours)), rgb);
+        }
+    }
+
+    #[test]
+    fn nan_is_black() {
+        assert_eq!(to_srgb8(to_srgb(Oklab::new(f32::NAN, 0.1, 0.1))), [0; 3]);
+        assert_eq!(to_srgb8(to_srgb(Oklab::new(0.5, f32::NAN, 0.0))), [0; 3]);
+        assert_eq!(
+            to_srgb8(to_srgb(Oklab::new(0.5, 0.0, f32::INFINITY))),
+            [0; 3]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! Coloring leaves so that no two neighbours match.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use palette::Oklab;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use wrong_track::{bsp, RegionCache};
+
+use crate::generate::Rng;
+
+/// One "RECOLOR": gives every leaf a color from `palette` so that no two
+/// leaves sharing an edge get the same one. `seed` picks between the many
+/// ways of doing that. `regions` has to be up to date with `bsp`.
+///
+/// Leaves sharing an edge make a planar graph, so five colors are always
+/// enough, and four nearly always are. With fewer, or when four don't work
+/// out, some neighbours end up matching; returns how many pairs do. Colors
+/// in `palette` more than once only count once.
+pub fn recolor(
+    bsp: &mut bsp::Bsp<Oklab>,
+    regions: &RegionCache,
+    palette: &[Oklab],
+    seed: u64,
+) -> usize {
+    // two entries with one color can't keep neighbours apart
+    let mut distinct: Vec<Oklab> = Vec::with_capacity(palette.len());
+    for &color in palette {
+        if !distinct.contains(&color) {
+            distinct.push(color);
+        }
+    }
+    let palette = distinct;
+    if palette.is_empty() {
+        return 0;
+    }
+    let mut rng = Rng::seed_from_u64(seed);
+
+    // sorted first so the result only depends on the tree and the seed, then
+    // shuffled so ties below go a different way for each seed
+    let mut leaves: Vec<bsp::BspKey> = regions.leaves().map(|(key, _)| key).collect();
+    leaves.sort();
+    leaves.shuffle(&mut rng);
+    let index: HashMap<bsp::BspKey, usize> =
+        leaves.iter().enumerate().map(|(i, &key)| (key, i)).collect();
+    let graph: Vec<Vec<usize>> = leaves
+        .iter()
+        .map(|&key| {
+            regions
+                .neighbours(key)
+                .iter()
+                .filter_map(|(neighbour, _)| index.get(neighbour).copied())
+                .collect()
+        })
+        .collect();
+
+    let colors = color_graph(&graph, palette.len(), &mut rng);
+    for (&key, &color) in leaves.iter().zip(&colors) {
+        if let Some(value) = bsp.leaf_value_mut(key) {
+            *value = palette[color];
+        }
+    }
+
+    clashes(&graph, &colors)
+}
+
+/// Keeps a recolored picture that way after the split at `split`: the new
+/// leaf takes the palette color nearest its own that none of its neighbours
+/// have. The other side keeps the split leaf's color, and only borders what
+/// that did and the new leaf. `regions` has to be up to date with `bsp`.
+pub fn keep_apart(
+    bsp: &mut bsp::Bsp<Oklab>,
+    regions: &RegionCache,
+    split: bsp::BspKey,
+    palette: &[Oklab],
+) {
+    // pruning may have undone the split
+    let leaf = match bsp.get(split) {
+        Some(bsp::BspNode::Inode(inode)) => inode.gt,
+        _ => return,
+    };
+    let color = match bsp.leaf_value(leaf) {
+        Some(&color) => color,
+        None => return,
+    };
+
+    let clashes = |c: &Oklab| {
+        regions
+            .neighbours(leaf)
+            .iter()
+            .filter(|&&(neighbour, _)| bsp.leaf_value(neighbour) == Some(c))
+            .count()
+    };
+    let distance = |c: &Oklab| {
+        let (l, a, b) = (c.l - color.l, c.a - color.a, c.b - color.b);
+        l * l + a * a + b * b
+    };
+    let nearest = palette
+        .iter()
+        .min_by(|x, y| {
+            clashes(x)
+                .cmp(&clashes(y))
+                .then(distance(x).total_cmp(&distance(y)))
+        })
+        .copied();
+
+    if let (Some(value), Some(nearest)) = (bsp.leaf_value_mut(leaf), nearest) {
+        *value = nearest;
+    }
+}
+
+// how far a color swap may spread before it's given up on, so a palette too
+// small to keep neighbours apart doesn't keep searching the whole picture
+const MAX_CHAIN: usize = 1000;
+
+// one of `num_colors` colors, which has to be at least 1, for every vertex,
+// keeping neighbours apart as far as that goes
+fn color_graph(graph: &[Vec<usize>], num_colors: usize, rng: &mut Rng) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..num_colors).collect();
+    order.shuffle(rng);
+    let mut colors = vec![None; graph.len()];
+    for v in smallest_last(graph).into_iter().rev() {
+        colors[v] = Some(pick(graph, &mut colors, v, &order));
+    }
+    colors.into_iter().map(|color| color.unwrap_or(0)).collect()
+}
+
+// how many pairs of neighbours have the same color
+fn clashes(graph: &[Vec<usize>], colors: &[usize]) -> usize {
+    graph
+        .iter()
+        .enumerate()
+        .flat_map(|(v, neighbours)| neighbours.iter().map(move |&u| (v, u)))
+        .filter(|&(v, u)| v < u && colors[v] == colors[u])
+        .count()
+}
+
+// every vertex, taking out the one with the fewest neighbours left each time.
+// A planar graph always has one with five at most, so coloring them in
+// reverse never finds more than five neighbours colored already.
+fn smallest_last(graph: &[Vec<usize>]) -> Vec<usize> {
+    let mut degree: Vec<usize> = graph.iter().map(Vec::len).collect();
+    let mut removed = vec![false; graph.len()];
+    let mut heap: BinaryHeap<_> = degree
+        .iter()
+        .enumerate()
+        .map(|(v, &d)| Reverse((d, v)))
+        .collect();
+
+    let mut order = Vec::with_capacity(graph.len());
+    while let Some(Reverse((d, v))) = heap.pop() {
+        // stale: it's lost neighbours since
+        if removed[v] || d != degree[v] {
+            continue;
+        }
+        removed[v] = true;
+        order.push(v);
+        for &u in &graph[v] {
+            if !removed[u] {
+                degree[u] -= 1;
+                heap.push(Reverse((degree[u], u)));
+            }
+        }
+    }
+    order
+}
+
+// a color none of `v`'s colored neighbours have, in `order` of preference,
+// swapping colors around it to free one up if it has to. If even that
+// fails, the one fewest neighbours have.
+fn pick(graph: &[Vec<usize>], colors: &mut [Option<usize>], v: usize, order: &[usize]) -> usize {
+    let mut used = vec![0; order.len()];
+    for &u in &graph[v] {
+        if let Some(c) = colors[u] {
+            used[c] += 1;
+        }
+    }
+    if let Some(&c) = order.iter().find(|&&c| used[c] == 0) {
+        return c;
+    }
+
+    for &a in order {
+        for &b in order {
+            if a != b && swap_chain(graph, colors, v, a, b) {
+                return a;
+            }
+        }
+    }
+    order.iter().copied().min_by_key(|&c| used[c]).unwrap_or(0)
+}
+
+// swaps `a` and `b` throughout the stretch of the graph colored only those
+// two that `v`'s neighbours colored `a` belong to, which leaves none of them
+// `a`. Unless that stretch reaches a neighbour colored `b` too, which would
+// just turn it `a`; then nothing changes.
+fn swap_chain(
+    graph: &[Vec<usize>],
+    colors: &mut [Option<usize>],
+    v: usize,
+    a: usize,
+    b: usize,
+) -> bool {
+    let mut stack: Vec<usize> = graph[v]
+        .iter()
+        .copied()
+        .filter(|&u| colors[u] == Some(a))
+        .collect();
+    let mut chain: HashSet<usize> = stack.iter().copied().collect();
+    while let Some(u) = stack.pop() {
+        for &w in &graph[u] {
+            let color = colors[w];
+            if (color == Some(a) || color == Some(b)) && chain.insert(w) {
+                if (color == Some(b) && graph[v].contains(&w)) || chain.len() > MAX_CHAIN {
+                    return false;
+                }
+                stack.push(w);
+            }
+        }
+    }
+
+    for u in chain {
+        colors[u] = if colors[u] == Some(a) { Some(b) } else { Some(a) };
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+    use rand::Rng as _;
+    use wrong_track::Polygon;
+
+    use super::*;
+
+    // a w by h grid of vertices with one diagonal across each square, which
+    // is planar and has vertices with six neighbours
+    fn triangulated_grid(w: usize, h: usize) -> Vec<Vec<usize>> {
+        let mut graph = vec![Vec::new(); w * h];
+        let mut link = |a: usize, b: usize| {
+            graph[a].push(b);
+            graph[b].push(a);
+        };
+        for y in 0..h {
+            for x in 0..w {
+                let v = y * w + x;
+                if x + 1 < w {
+                    link(v, v + 1);
+                }
+                if y + 1 < h {
+                    link(v, v + w);
+                }
+                if x + 1 < w && y + 1 < h {
+                    link(v, v + w + 1);
+                }
+            }
+        }
+        graph
+    }
+
+    fn random_tree(seed: u64, splits: usize) -> (bsp::Bsp<Oklab>, RegionCache) {
+        let mut rng = Rng::seed_from_u64(seed);
+        let mut bsp = bsp::Bsp::new(Oklab::new(0.5, 0.0, 0.0));
+        for _ in 0..splits {
+            let point = Vec2::new(rng.gen(), rng.gen());
+            let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+            let normal = Vec2::new(angle.cos(), angle.sin());
+            bsp.split_at_point(point, normal, Oklab::new(0.5, 0.0, 0.0));
+        }
+        let regions = RegionCache::new(&bsp, Polygon::new_rect(Vec2::ZERO, Vec2::ONE));
+        (bsp, regions)
+    }
+
+    fn palette(n: usize) -> Vec<Oklab> {
+        (0..n).map(|i| Oklab::new(i as f32 / 10.0, 0.0, 0.0)).collect()
+    }
+
+    fn leaf_colors(bsp: &bsp::Bsp<Oklab>, regions: &RegionCache) -> Vec<Oklab> {
+        regions
+            .leaves()
+            .map(|(key, _)| *bsp.leaf_value(key).unwrap())
+            .collect()
+    }
+
+    // pairs of leaves sharing an edge and a color
+    fn matching_neighbours(bsp: &bsp::Bsp<Oklab>, regions: &RegionCache) -> usize {
+        regions
+            .leaves()
+            .flat_map(|(key, _)| {
+                regions
+                    .neighbours(key)
+                    .iter()
+                    .filter_map(move |&(other, _)| {
+                        (key < other && bsp.leaf_value(key) == bsp.leaf_value(other)).then_some(())
+                    })
+            })
+            .count()
+    }
+
+    #[test]
+    fn kempe_swap_frees_a_color() {
+        // a hub whose four rim neighbours already use all four colors
+        let graph = vec![
+            vec![1, 2, 3, 4],
+            vec![0, 2, 4],
+            vec![0, 1, 3],
+            vec![0, 2, 4],
+            vec![0, 3, 1],
+        ];
+        let mut colors = vec![None, Some(0), Some(1), Some(2), Some(3)];
+        let order = [0, 1, 2, 3];
+        let c = pick(&graph, &mut colors, 0, &order);
+        colors[0] = Some(c);
+
+        let colors: Vec<usize> = colors.into_iter().map(Option::unwrap).collect();
+        assert_eq!(clashes(&graph, &colors), 0);
+    }
+
+    #[test]
+    fn four_and_five_colors_keep_neighbours_apart() {
+        let graph = triangulated_grid(30, 30);
+        for num_colors in [4, 5] {
+            for seed in 0..10 {
+                let colors = color_graph(&graph, num_colors, &mut Rng::seed_from_u64(seed));
+                assert!(colors.iter().all(|&c| c < num_colors));
+                assert_eq!(clashes(&graph, &colors), 0, "{} colors, seed {}", num_colors, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn four_and_five_colors_recolor_a_tree() {
+        let (mut bsp, regions) = random_tree(1, 300);
+        for num_colors in [4, 5] {
+            assert_eq!(recolor(&mut bsp, &regions, &palette(num_colors), 3), 0);
+        }
+    }
+
+    #[test]
+    fn too_few_colors_count_the_clashes() {
+        let graph = triangulated_grid(10, 10);
+        let edges = graph.iter().map(Vec::len).sum::<usize>() / 2;
+
+        let colors = color_graph(&graph, 1, &mut Rng::seed_from_u64(0));
+        assert_eq!(clashes(&graph, &colors), edges);
+
+        for num_colors in 2..=3 {
+            let colors = color_graph(&graph, num_colors, &mut Rng::seed_from_u64(0));
+            assert!(colors.iter().all(|&c| c < num_colors));
+            // every triangle has to have a clash with two colors
+            if num_colors == 2 {
+                assert!(clashes(&graph, &colors) > 0);
+            }
+        }
+
+        let (mut bsp, regions) = random_tree(2, 200);
+        for num_colors in 1..=3 {
+            let clashed = recolor(&mut bsp, &regions, &palette(num_colors), 5);
+            let counted = matching_neighbours(&bsp, &regions);
+            assert_eq!(clashed, counted, "{} colors", num_colors);
+        }
+    }
+
+    #[test]
+    fn duplicate_colors_count_once() {
+        let (mut bsp, regions) = random_tree(5, 200);
+
+        // what "Add color" makes: the last color again
+        let mut four = palette(4);
+        four.push(four[3]);
+        assert_eq!(recolor(&mut bsp, &regions, &four, 1), 0);
+        assert_eq!(matching_neighbours(&bsp, &regions), 0);
+
+        let mut three = palette(3);
+        three.push(three[2]);
+        let clashed = recolor(&mut bsp, &regions, &three, 1);
+        assert_eq!(clashed, matching_neighbours(&bsp, &regions));
+        let mut bsp_three = bsp.clone();
+        assert_eq!(recolor(&mut bsp_three, &regions, &palette(3), 1), clashed);
+    }
+
+    #[test]
+    fn empty_palette_changes_nothing() {
+        let (mut bsp, regions) = random_tree(3, 50);
+        let before = leaf_colors(&bsp, &regions);
+        assert_eq!(recolor(&mut bsp, &regions, &[], 0), 0);
+        assert_eq!(leaf_colors(&bsp, &regions), before);
+    }
+
+    #[test]
+    fn same_seed_same_colors() {
+        let (bsp, regions) = random_tree(4, 200);
+        let run = |seed| {
+            let mut bsp = bsp.clone();
+            let clashed = recolor(&mut bsp, &regions, &palette(4), seed);
+            (clashed, leaf_colors(&bsp, &regions))
+        };
+        assert_eq!(run(9), run(9));
+    }
+}
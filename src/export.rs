@@ -0,0 +1,109 @@
+//! Turning trees into pictures.
+
+use palette::{LinSrgb, Oklab, Srgb};
+
+use crate::bsp;
+use crate::color::{to_srgb, to_srgb8};
+use crate::geom::{canvas_size, Polygon};
+use crate::style::Style;
+
+/// Leaf values that can be drawn.
+///
+/// Implemented for the palette color types we use; implement it for your own
+/// leaf type to export trees of it.
+pub trait Fill {
+    /// The color to paint a leaf's region with.
+    fn fill(&self) -> Srgb;
+}
+impl Fill for Oklab {
+    fn fill(&self) -> Srgb {
+        to_srgb(*self)
+    }
+}
+impl Fill for Srgb {
+    fn fill(&self) -> Srgb {
+        *self
+    }
+}
+impl Fill for LinSrgb {
+    fn fill(&self) -> Srgb {
+        Srgb::from_linear(*self)
+    }
+}
+
+/// Writes every leaf as a filled path in an SVG of `width` x `height`.
+/// The viewBox is the [`canvas_size`] for that size.
+///
+/// Outlines are paths filled with the outline color, in a group under all
+/// the fills, the same shapes every renderer draws (see [`Style`]).
+pub fn save_svg<T: Fill>(bsp: &bsp::Bsp<T>, width: u32, height: u32, style: &Style) -> Vec<u8> {
+    use svg::node::element::path::Data;
+    use svg::node::element::{Group, Path, Rectangle};
+    use svg::Document;
+
+    let canvas = canvas_size(width, height);
+    let mut document = Document::new()
+        .set("width", width)
+        .set("height", height)
+        .set("viewBox", (0.0, 0.0, canvas.x, canvas.y));
+
+    if let Some(color) = style.gutter_color {
+        document = document.add(
+            Rectangle::new()
+                .set("width", canvas.x)
+                .set("height", canvas.y)
+                .set("fill", svg_color(color)),
+        );
+    }
+
+    let mut outlines = Vec::new();
+    let mut leaves = Vec::new();
+    bsp.visit_leaf_polygons(
+        bsp.root_key(),
+        Polygon::new_rect(glam::Vec2::ZERO, canvas),
+        &mut |leaf, poly| {
+            outlines.extend(style.outline_region(poly));
+            let fill = style.fill_region(poly);
+            if fill.vertices.len() >= 3 {
+                leaves.push((leaf.0.fill(), fill));
+            }
+        },
+    );
+
+    let path_data = |poly: &Polygon| {
+        let mut data = Data::new();
+        data = data.move_to((poly.vertices[0].x, poly.vertices[0].y));
+        for vert in &poly.vertices[1..] {
+            data = data.line_to((vert.x, vert.y));
+        }
+        data.close()
+    };
+
+    if !outlines.is_empty() {
+        let mut group = Group::new()
+            .set("fill", svg_color(style.outline_color))
+            .set("stroke", "none");
+        for poly in &outlines {
+            group = group.add(Path::new().set("d", path_data(poly)));
+        }
+        document = document.add(group);
+    }
+
+    for (color, poly) in &leaves {
+        let path = Path::new()
+            .set("fill", svg_color(*color))
+            .set("stroke", "none")
+            .set("stroke-width", 0)
+            .set("d", path_data(poly));
+        document = document.add(path);
+    }
+
+    let mut w = Vec::new();
+    svg::write(&mut w, &document).unwrap();
+    w
+}
+
+fn svg_color(color: Srgb) -> String {
+    let [r, g, b] = to_srgb8(color);
+    format!("rgb({}, {}, {})", r, g, b)
+}
@@ -0,0 +1,391 @@
+use glam::Vec2;
+use palette::{Mix, Oklab, Srgb};
+use rand::Rng as _;
+use serde::{Deserialize, Serialize};
+
+use wrong_track::{bsp, RegionCache, SplitConstraints};
+
+/// The RNG behind every random operation. Seeded explicitly so a seed plus a
+/// list of edits always rebuilds the same picture.
+pub type Rng = rand_chacha::ChaCha8Rng;
+
+/// How many random splits are tried before giving up on finding one that
+/// fits the constraints.
+pub const MAX_ATTEMPTS: usize = 100;
+
+/// The knobs behind every random split. Shared by the side panel and the
+/// command line, so the same settings and RNG give the same picture.
+#[derive(Debug, Clone)]
+pub struct SplitParams {
+    pub normal_randomness: f32,
+    pub color_randomness: f32,
+    pub num_color_samples: usize,
+    pub color_source: ColorSource,
+    pub override_color: Option<Oklab>,
+    pub palette_mode: PaletteMode,
+    /// What `palette_mode` picks from: colors, and how likely each is next
+    /// to the others.
+    pub palette: Vec<(Oklab, f32)>,
+    /// Where splits can land: the rectangle from the origin to here.
+    pub canvas: Vec2,
+    /// What each split has to leave on either side; random splits that
+    /// don't fit are rolled again.
+    pub constraints: SplitConstraints,
+    /// Prune what each split leaves behind with this minimum area, in canvas
+    /// units squared (see [`bsp::Bsp::prune`]). `None` leaves it be.
+    pub prune: Option<f32>,
+}
+
+/// What a new region's color starts out from, before `color_randomness`
+/// mixes a random one in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorSource {
+    /// The average of `num_color_samples` points picked around the split.
+    #[default]
+    Samples,
+    /// The average of the split leaf's neighbours, weighted by how much edge
+    /// each shares with it.
+    Neighbours,
+}
+
+/// Whether new colors are kept to a palette.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteMode {
+    /// Any color goes.
+    #[default]
+    Off,
+    /// A palette color drawn at random, going by the weights.
+    Pick,
+    /// The color there would have been, moved to the nearest palette color.
+    /// Heavier colors reach further.
+    Snap,
+}
+
+/// Converts an 8-bit sRGB color, like the override color picker's, so that
+/// [`wrong_track::to_srgb8`] gives the same one back.
+pub fn color_from_srgb8([r, g, b]: [u8; 3]) -> Oklab {
+    wrong_track::from_srgb(Srgb::new(r, g, b).into_format())
+}
+
+pub fn random_point(rng: &mut Rng, canvas: Vec2) -> Vec2 {
+    Vec2::new(rng.gen_range(0.0f32..1.0), rng.gen_range(0.0f32..1.0)) * canvas
+}
+
+// std's trig comes from the platform's libm, which differs between native
+// and wasm in the last bit. libm is the same code everywhere, which is also
+// why colors come in through wrong_track::from_srgb.
+fn sin_cos(angle: f32) -> (f32, f32) {
+    libm::sincosf(angle)
+}
+
+pub fn random_point_in_disk(rng: &mut Rng, radius: f32) -> Vec2 {
+    let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+    let distance = rng.gen_range(0.0..radius);
+    let (x, y) = sin_cos(angle);
+    glam::vec2(x, y) * distance
+}
+
+/// A color for a new region at `point`. `regions` has to be up to date with
+/// `bsp`.
+pub fn random_color(
+    bsp: &bsp::Bsp<Oklab>,
+    regions: &RegionCache,
+    rng: &mut Rng,
+    params: &SplitParams,
+    point: Vec2,
+) -> Oklab {
+    if let Some(color) = params.override_color {
+        return color;
+    }
+    if params.palette_mode == PaletteMode::Pick {
+        if let Some(color) = pick_weighted(rng, &params.palette) {
+            return color;
+        }
+    }
+
+    let random_color = wrong_track::from_srgb(Srgb::new(
+        rng.gen_range(0.0f32..1.0),
+        rng.gen_range(0.0f32..1.0),
+        rng.gen_range(0.0f32..1.0),
+    ));
+    let sampled_color = match params.color_source {
+        ColorSource::Samples => {
+            let mut sampled_color = Oklab::default();
+            for _ in 0..params.num_color_samples {
+                let perturb = random_point_in_disk(rng, 0.05);
+                let sample_point = (point + perturb).clamp(Vec2::ZERO, params.canvas);
+                let sample = bsp.get_at_point(sample_point);
+                sampled_color += *sample;
+            }
+            sampled_color * (1.0 / (params.num_color_samples as f32))
+        }
+        ColorSource::Neighbours => {
+            let leaf = bsp.leaf_index_for_point(point);
+            // a lone root has no neighbours to go on
+            neighbour_average(bsp, regions, leaf).unwrap_or(*bsp.get_at_point(point))
+        }
+    };
+
+    let color = sampled_color.mix(&random_color, params.color_randomness);
+    match params.palette_mode {
+        PaletteMode::Snap => snap(&params.palette, color).unwrap_or(color),
+        _ => color,
+    }
+}
+
+pub fn random_normal(rng: &mut Rng, params: &SplitParams, point: Vec2) -> Vec2 {
+    let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+    let (x, y) = sin_cos(angle);
+    let rand_normal = glam::vec2(x, y);
+
+    let rand_normal = (point - params.canvas * 0.5).lerp(rand_normal, params.normal_randomness);
+    rand_normal.normalize()
+}
+
+/// One "RND SPLIT": a random point, a random normal, a random color, tried
+/// again until the split fits the constraints.
+///
+/// `regions` has to be up to date with `bsp`, and needs updating below the
+/// returned key afterwards. That's the leaf that got split, which may be a
+/// leaf again if pruning undid the split, or `None` if nothing fit in
+/// [`MAX_ATTEMPTS`] tries.
+pub fn random_split(
+    bsp: &mut bsp::Bsp<Oklab>,
+    regions: &RegionCache,
+    rng: &mut Rng,
+    params: &SplitParams,
+) -> Option<bsp::BspKey> {
+    for _ in 0..MAX_ATTEMPTS {
+        let rand_point = random_point(rng, params.canvas);
+        let rand_normal = random_normal(rng, params, rand_point);
+        let rand_color = random_color(bsp, regions, rng, params, rand_point);
+
+        let region = match regions.region(bsp.leaf_index_for_point(rand_point)) {
+            Some(region) => region,
+            None => continue,
+        };
+        let split = bsp.try_split_at_point(
+            rand_point,
+            rand_normal,
+            rand_color,
+            region,
+            &params.constraints,
+        );
+        if let Ok(key) = split {
+            // the split leaf's region is the same as before
+            if let Some(min_area) = params.prune {
+                bsp.prune(key, region.clone(), min_area);
+            }
+            return Some(key);
+        }
+    }
+    None
+}
+
+/// One "SMOOTH": moves every leaf's color `strength` of the way towards the
+/// average of its neighbours', weighted by how much edge each shares with
+/// it, `iterations` times over. Each pass looks at the colors from the one
+/// before, so the order leaves are visited in doesn't matter.
+pub fn smooth(
+    bsp: &mut bsp::Bsp<Oklab>,
+    regions: &RegionCache,
+    strength: f32,
+    iterations: usize,
+) {
+    for _ in 0..iterations {
+        let targets: Vec<(bsp::BspKey, Oklab)> = regions
+            .leaves()
+            .filter_map(|(key, _)| Some((key, neighbour_average(bsp, regions, key)?)))
+            .collect();
+        for (key, target) in targets {
+            if let Some(color) = bsp.leaf_value_mut(key) {
+                *color = color.mix(&target, strength);
+            }
+        }
+    }
+}
+
+// the colors around the leaf `key`, weighted by shared edge length
+fn neighbour_average(
+    bsp: &bsp::Bsp<Oklab>,
+    regions: &RegionCache,
+    key: bsp::BspKey,
+) -> Option<Oklab> {
+    let mut sum = Oklab::default();
+    let mut total = 0.0;
+    for &(neighbour, length) in regions.neighbours(key) {
+        if let Some(&color) = bsp.leaf_value(neighbour) {
+            sum += color * length;
+            total += length;
+        }
+    }
+    (total > 0.0).then(|| sum * (1.0 / total))
+}
+
+// a color drawn from `palette` as often as its weight says, if any weigh
+// anything
+fn pick_weighted(rng: &mut Rng, palette: &[(Oklab, f32)]) -> Option<Oklab> {
+    let total: f32 = palette.iter().map(|&(_, weight)| weight).sum();
+    if !total.is_finite() || total <= 0.0 {
+        return None;
+    }
+
+    let mut x = rng.gen_range(0.0..total);
+    for &(color, weight) in palette {
+        if x < weight {
+            return Some(color);
+        }
+        x -= weight;
+    }
+    // rounding can leave a sliver past the end
+    palette.iter().rev().find(|&&(_, weight)| weight > 0.0).map(|&(color, _)| color)
+}
+
+// the palette color nearest `color`, distances shrunk by weight, leaving out
+// colors that weigh nothing
+fn snap(palette: &[(Oklab, f32)], color: Oklab) -> Option<Oklab> {
+    let distance = |&(c, weight): &(Oklab, f32)| {
+        let (l, a, b) = (c.l - color.l, c.a - color.a, c.b - color.b);
+        (l * l + a * a + b * b) / weight
+    };
+    palette
+        .iter()
+        .filter(|&&(_, weight)| weight > 0.0)
+        .min_by(|x, y| distance(x).total_cmp(&distance(y)))
+        .map(|&(c, _)| c)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng as _;
+    use wrong_track::Polygon;
+
+    use super::*;
+
+    fn params() -> SplitParams {
+        let palette = [[200, 40, 40], [40, 200, 40], [40, 40, 200]];
+        SplitParams {
+            normal_randomness: 0.5,
+            color_randomness: 0.3,
+            num_color_samples: 3,
+            color_source: ColorSource::Samples,
+            override_color: None,
+            palette_mode: PaletteMode::Snap,
+            palette: palette.map(|c| (color_from_srgb8(c), 1.0)).to_vec(),
+            canvas: Vec2::new(1.0, 0.75),
+            constraints: SplitConstraints::default(),
+            prune: None,
+        }
+    }
+
+    fn regions(bsp: &bsp::Bsp<Oklab>, params: &SplitParams) -> RegionCache {
+        RegionCache::new(bsp, Polygon::new_rect(Vec2::ZERO, params.canvas))
+    }
+
+    fn splits(bsp: &mut bsp::Bsp<Oklab>, rng: &mut Rng, params: &SplitParams, n: usize) {
+        let mut regions = regions(bsp, params);
+        for _ in 0..n {
+            if let Some(key) = random_split(bsp, &regions, rng, params) {
+                regions.update_subtree(bsp, key);
+            }
+        }
+    }
+
+    fn json(bsp: &bsp::Bsp<Oklab>) -> String {
+        serde_json::to_string(bsp).unwrap()
+    }
+
+    #[test]
+    fn same_seed_same_tree() {
+        let params = params();
+        let run = || {
+            let mut bsp = bsp::Bsp::new(Oklab::new(1.0, 0.0, 0.0));
+            splits(&mut bsp, &mut Rng::seed_from_u64(7), &params, 60);
+            json(&bsp)
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn rng_position_picks_up_where_it_left_off() {
+        let params = params();
+        let mut rng = Rng::seed_from_u64(7);
+        let mut bsp = bsp::Bsp::new(Oklab::new(1.0, 0.0, 0.0));
+        splits(&mut bsp, &mut rng, &params, 30);
+
+        // what a project file keeps
+        let saved = json(&bsp);
+        let position = rng.get_word_pos() as u64;
+
+        splits(&mut bsp, &mut rng, &params, 30);
+
+        let mut loaded: bsp::Bsp<Oklab> = serde_json::from_str(&saved).unwrap();
+        let mut rng = Rng::seed_from_u64(7);
+        rng.set_word_pos(position.into());
+        splits(&mut loaded, &mut rng, &params, 30);
+        assert_eq!(json(&loaded), json(&bsp));
+    }
+
+    // leaves across the unit square with these lightnesses, left to right
+    fn stripes(lightness: &[f32]) -> (bsp::Bsp<Oklab>, RegionCache, Vec<Vec2>) {
+        let gray = |l| Oklab::new(l, 0.0, 0.0);
+        let width = 1.0 / lightness.len() as f32;
+        let mut bsp = bsp::Bsp::new(gray(lightness[0]));
+        for (i, &l) in lightness.iter().enumerate().skip(1) {
+            bsp.split_at_point(Vec2::new(i as f32 * width, 0.5), Vec2::X, gray(l));
+        }
+        let regions = RegionCache::new(&bsp, Polygon::new_rect(Vec2::ZERO, Vec2::ONE));
+        let centers = (0..lightness.len())
+            .map(|i| Vec2::new((i as f32 + 0.5) * width, 0.5))
+            .collect();
+        (bsp, regions, centers)
+    }
+
+    fn lightness_at(bsp: &bsp::Bsp<Oklab>, points: &[Vec2]) -> Vec<f32> {
+        points
+            .iter()
+            .map(|&point| bsp.get_at_point(point).l)
+            .collect()
+    }
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-6, "{:?} vs {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn smoothing_pulls_neighbours_together() {
+        let (mut bsp, regions, centers) = stripes(&[0.2, 0.8]);
+        smooth(&mut bsp, &regions, 0.25, 1);
+        assert_close(&lightness_at(&bsp, &centers), &[0.35, 0.65]);
+
+        smooth(&mut bsp, &regions, 0.25, 10);
+        let l = lightness_at(&bsp, &centers);
+        assert!(l[0] < l[1] && l[1] - l[0] < 0.01, "{:?}", l);
+    }
+
+    #[test]
+    fn each_pass_sees_the_last_ones_colors() {
+        // the middle averages the old outsides, not whichever was done first
+        let (mut bsp, regions, centers) = stripes(&[0.0, 0.2, 1.0]);
+        smooth(&mut bsp, &regions, 1.0, 1);
+        assert_close(&lightness_at(&bsp, &centers), &[0.2, 0.5, 0.2]);
+
+        smooth(&mut bsp, &regions, 1.0, 1);
+        assert_close(&lightness_at(&bsp, &centers), &[0.5, 0.2, 0.5]);
+    }
+
+    #[test]
+    fn no_iterations_no_change() {
+        let params = params();
+        let mut bsp = bsp::Bsp::new(Oklab::new(1.0, 0.0, 0.0));
+        splits(&mut bsp, &mut Rng::seed_from_u64(3), &params, 50);
+        let regions = regions(&bsp, &params);
+
+        let before = json(&bsp);
+        smooth(&mut bsp, &regions, 0.5, 0);
+        assert_eq!(json(&bsp), before);
+    }
+}
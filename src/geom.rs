@@ -0,0 +1,969 @@
+//! 2D geometry: the lines trees are split along and the regions they carve out.
+
+use glam::*;
+use serde::{Deserialize, Serialize};
+
+/// A line in the plane, splitting it into two half-planes.
+///
+/// Points with `distance_to_point(p) <= 0` are on the "le" side, the rest are
+/// on the "gt" side.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plane {
+    /// Unit normal, pointing towards the "gt" side.
+    pub normal: Vec2,
+    /// Signed distance of the line from the origin, along `normal`.
+    pub distance: f32,
+}
+impl Plane {
+    /// The line through `point` with unit normal `normal`.
+    pub fn through(point: Vec2, normal: Vec2) -> Plane {
+        Plane {
+            normal,
+            distance: point.dot(normal),
+        }
+    }
+
+    /// Signed distance from the line; positive on the side `normal` points to.
+    pub fn distance_to_point(&self, point: Vec2) -> f32 {
+        point.dot(self.normal) - self.distance
+    }
+
+    /// Where the segment from `start` to `end` crosses the line, or `None`
+    /// if both ends are on the same side.
+    pub fn line_intersection(&self, (start, end): (Vec2, Vec2)) -> Option<Vec2> {
+        let start_dist = self.distance_to_point(start);
+        let end_dist = self.distance_to_point(end);
+
+        if (start_dist > 0.0) == (end_dist > 0.0) {
+            return None;
+        }
+
+        // the ends are on opposite sides, so this only fails for NaNs
+        let frac = start_dist / (start_dist - end_dist);
+        (0.0..=1.0).contains(&frac).then(|| start.lerp(end, frac))
+    }
+}
+
+/// How close, relative to the size of the coordinates involved, a point has
+/// to be to a plane to count as on it when clipping.
+pub const EPSILON: f32 = 1e-6;
+
+// where a vertex is relative to the plane being clipped against
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Keep,
+    On,
+    Clip,
+}
+
+/// The canvas a `width` x `height` document is drawn on: a rectangle from the
+/// origin to the returned corner, with the long side 1. Every square document
+/// gets the unit square, whatever its size in pixels.
+pub fn canvas_size(width: u32, height: u32) -> Vec2 {
+    let size = Vec2::new(width as f32, height as f32);
+    size / size.max_element()
+}
+
+/// A convex polygon.
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    /// Vertices in order. Regions of a tree all wind the same way as
+    /// [`Polygon::new_rect`], since clipping keeps the winding: see
+    /// [`Polygon::winding`]. May be empty, when clipping removed everything.
+    pub vertices: Vec<Vec2>,
+}
+
+/// Which way a polygon's vertices go around, as seen on screen with y
+/// pointing down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winding {
+    /// Positive [`Polygon::signed_area`].
+    Clockwise,
+    /// Negative [`Polygon::signed_area`]. [`Polygon::new_rect`] winds this
+    /// way, and so does every region clipped from it.
+    CounterClockwise,
+}
+impl Polygon {
+    /// An axis-aligned rectangle spanning `min` to `max`.
+    pub fn new_rect(min: Vec2, max: Vec2) -> Polygon {
+        Polygon {
+            vertices: vec![min, Vec2::new(min.x, max.y), max, Vec2::new(max.x, min.y)],
+        }
+    }
+    /// The area enclosed, whichever way the vertices wind.
+    pub fn area(&self) -> f32 {
+        self.signed_area().abs()
+    }
+    /// The area enclosed, positive or negative depending on the winding.
+    pub fn signed_area(&self) -> f32 {
+        let origin = match self.vertices.first() {
+            Some(&first) => first,
+            None => return 0.0,
+        };
+        // measured from a vertex rather than the origin, which keeps
+        // precision for small polygons far from it
+        self.edges()
+            .map(|(a, b)| (a - origin).perp_dot(b - origin))
+            .sum::<f32>()
+            * 0.5
+    }
+    /// Which way the vertices go around, or `None` if they don't enclose
+    /// anything: fewer than three vertices, or all of them on one line.
+    pub fn winding(&self) -> Option<Winding> {
+        let signed_area = self.signed_area();
+        // relative to the size, so tiny but proper polygons still count
+        let tolerance = self.perimeter().powi(2) * 1e-7;
+        if self.vertices.len() < 3 || signed_area.abs() <= tolerance {
+            None
+        } else if signed_area > 0.0 {
+            Some(Winding::Clockwise)
+        } else {
+            Some(Winding::CounterClockwise)
+        }
+    }
+    /// Whether every corner turns the same way. Straight corners and
+    /// repeated vertices, which clipping leaves behind, are allowed; polygons
+    /// that don't enclose anything are not convex.
+    pub fn is_convex(&self) -> bool {
+        let sign = match self.winding() {
+            Some(Winding::Clockwise) => 1.0,
+            Some(Winding::CounterClockwise) => -1.0,
+            None => return false,
+        };
+
+        let n = self.vertices.len();
+        (0..n).all(|i| {
+            let a = self.vertices[i];
+            let b = self.vertices[(i + 1) % n];
+            let c = self.vertices[(i + 2) % n];
+            let (ab, bc) = (b - a, c - b);
+            ab.perp_dot(bc) * sign >= -1e-5 * ab.length() * bc.length()
+        })
+    }
+    /// How far from a sliver the polygon is: its narrowest width over its
+    /// length across that, so 1 for a square and close to 0 for a sliver.
+    /// 0 for polygons that don't enclose anything. Assumes the polygon is
+    /// convex.
+    pub fn aspect_ratio(&self) -> f32 {
+        if self.winding().is_none() {
+            return 0.0;
+        }
+
+        let extent = |direction: Vec2| {
+            let (lo, hi) = self
+                .vertices
+                .iter()
+                .map(|v| v.dot(direction))
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), d| {
+                    (lo.min(d), hi.max(d))
+                });
+            hi - lo
+        };
+        // a convex polygon is narrowest straight across from one of its edges
+        let (width, length) = self
+            .edges()
+            .filter_map(|(a, b)| (b - a).try_normalize())
+            .map(|along| (extent(along.perp()), extent(along)))
+            .fold((f32::INFINITY, 0.0), |narrowest, candidate| {
+                if candidate.0 < narrowest.0 {
+                    candidate
+                } else {
+                    narrowest
+                }
+            });
+        width.min(length) / width.max(length)
+    }
+    /// Total length of the edges.
+    pub fn perimeter(&self) -> f32 {
+        self.edges().map(|(a, b)| a.distance(b)).sum()
+    }
+    /// The center of mass, or `None` for an empty polygon. Polygons that
+    /// don't enclose anything get the average of their vertices instead.
+    pub fn centroid(&self) -> Option<Vec2> {
+        if self.vertices.is_empty() {
+            return None;
+        }
+        if self.winding().is_none() {
+            let sum: Vec2 = self.vertices.iter().sum();
+            return Some(sum / self.vertices.len() as f32);
+        }
+
+        // measured from a vertex rather than the origin, which keeps
+        // precision for small polygons far from it
+        let origin = self.vertices[0];
+        let mut weighted = Vec2::ZERO;
+        let mut twice_area = 0.0;
+        for (a, b) in self.edges() {
+            let (a, b) = (a - origin, b - origin);
+            let cross = a.perp_dot(b);
+            weighted += (a + b) * cross;
+            twice_area += cross;
+        }
+        Some(origin + weighted / (3.0 * twice_area))
+    }
+    /// The smallest axis-aligned rectangle holding every vertex, as its
+    /// min and max corners. `None` for an empty polygon.
+    pub fn bounds(&self) -> Option<(Vec2, Vec2)> {
+        let first = *self.vertices.first()?;
+        Some(
+            self.vertices
+                .iter()
+                .fold((first, first), |(min, max), &v| (min.min(v), max.max(v))),
+        )
+    }
+    /// Whether `point` is inside or on the edge. Assumes the polygon is
+    /// convex, which everything a tree produces is.
+    pub fn contains(&self, point: Vec2) -> bool {
+        let sign = match self.winding() {
+            Some(Winding::Clockwise) => 1.0,
+            Some(Winding::CounterClockwise) => -1.0,
+            None => return false,
+        };
+        self.edges().all(|(a, b)| {
+            let edge = b - a;
+            // a little slack, so points on a shared edge count for both sides
+            (edge.perp_dot(point - a) * sign) >= -1e-6 * edge.length()
+        })
+    }
+    /// The stretch of `plane`'s line that the polygon runs along, as
+    /// distances along `plane.normal.perp()`, or `None` if it only touches
+    /// the line at a corner or not at all. Vertices within [`EPSILON`] of
+    /// the line count as on it.
+    ///
+    /// Only meaningful for polygons on one side of the line, as the regions
+    /// on either side of a split are.
+    pub fn span_along(&self, plane: &Plane) -> Option<(f32, f32)> {
+        let tolerance = self.tolerance();
+        let along = plane.normal.perp();
+        let (lo, hi) = self
+            .vertices
+            .iter()
+            .filter(|&&v| plane.distance_to_point(v).abs() <= tolerance)
+            .map(|v| v.dot(along))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), d| {
+                (lo.min(d), hi.max(d))
+            });
+        (hi - lo > tolerance).then_some((lo, hi))
+    }
+    // how close counts as the same point; rounding errors grow with the
+    // coordinates, so this does too
+    fn tolerance(&self) -> f32 {
+        let scale = self
+            .vertices
+            .iter()
+            .fold(1.0, |scale: f32, v| scale.max(v.abs().max_element()));
+        EPSILON * scale
+    }
+    // each vertex and the next, wrapping around
+    fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let n = self.vertices.len();
+        (0..n).map(move |i| (self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+    /// The part of `plane`'s line inside this polygon, as its two ends.
+    /// `None` if the line misses.
+    pub fn cut_line(&self, plane: &Plane) -> Option<(Vec2, Vec2)> {
+        let n = self.vertices.len();
+        let mut ends = Vec::with_capacity(2);
+        for i in 0..n {
+            let (a, b) = (self.vertices[i], self.vertices[(i + 1) % n]);
+            let (da, db) = (plane.distance_to_point(a), plane.distance_to_point(b));
+            // same side test as everywhere else: on the line counts as "le"
+            if (da <= 0.0) != (db <= 0.0) {
+                ends.push(a + (b - a) * (da / (da - db)));
+            }
+        }
+
+        match ends[..] {
+            [a, b] => Some((a, b)),
+            _ => None,
+        }
+    }
+    /// Cuts away one side of `plane`: the "gt" side if `clipside_is_greater`,
+    /// else the "le" side.
+    ///
+    /// Vertices within [`EPSILON`] of the plane count as on it, and are kept
+    /// on both sides rather than cut, so nearly parallel cuts and cuts through
+    /// a corner don't leave slivers or doubled-up vertices. Whatever doesn't
+    /// enclose any area, including a polygon with fewer than three vertices
+    /// to begin with, comes out empty.
+    pub fn clip_against_plane(&self, plane: &Plane, clipside_is_greater: bool) -> Polygon {
+        let empty = Polygon { vertices: vec![] };
+        if self.vertices.len() < 3 {
+            return empty;
+        }
+
+        let tolerance = self.tolerance();
+        let side = |v: Vec2| {
+            let distance = plane.distance_to_point(v);
+            // towards the clip side; NaNs fall through to `On` and are kept
+            let distance = if clipside_is_greater {
+                distance
+            } else {
+                -distance
+            };
+            if distance > tolerance {
+                Side::Clip
+            } else if distance < -tolerance {
+                Side::Keep
+            } else {
+                Side::On
+            }
+        };
+
+        let mut vertices = Vec::with_capacity(self.vertices.len() + 1);
+        let mut prev = self.vertices[self.vertices.len() - 1];
+        let mut prev_side = side(prev);
+        for &current in &self.vertices {
+            let current_side = side(current);
+
+            // only edges going properly through the plane get cut; one
+            // ending on it already has its vertex there
+            if let (Side::Keep, Side::Clip) | (Side::Clip, Side::Keep) = (prev_side, current_side) {
+                vertices.extend(plane.line_intersection((prev, current)));
+            }
+            if current_side != Side::Clip {
+                vertices.push(current);
+            }
+
+            prev = current;
+            prev_side = current_side;
+        }
+
+        vertices.dedup_by(|a, b| a.distance(*b) <= tolerance);
+        while vertices.len() > 1 && vertices[0].distance(vertices[vertices.len() - 1]) <= tolerance
+        {
+            vertices.pop();
+        }
+
+        let clipped = Polygon { vertices };
+        if clipped.winding().is_none() {
+            return empty;
+        }
+        clipped
+    }
+    /// Shrinks the polygon by moving every edge `distance` inwards. Comes
+    /// out empty if that's more than the polygon has room for.
+    pub fn inset(&self, distance: f32) -> Polygon {
+        let (vertices, normals) = match self.outward_normals() {
+            Some(found) => found,
+            None => return Polygon { vertices: vec![] },
+        };
+        if distance <= 0.0 {
+            return self.clone();
+        }
+
+        let mut inset = self.clone();
+        for (&v, &normal) in vertices.iter().zip(&normals) {
+            let plane = Plane {
+                normal,
+                distance: v.dot(normal) - distance,
+            };
+            inset = inset.clip_against_plane(&plane, true);
+            if inset.vertices.len() < 3 {
+                return Polygon { vertices: vec![] };
+            }
+        }
+        inset
+    }
+    /// Grows the polygon by moving every edge `distance` outwards, filling
+    /// the corners in the style of `join`. The result is still convex.
+    pub fn outset(&self, distance: f32, join: Join) -> Polygon {
+        let (vertices, normals) = match self.outward_normals() {
+            Some(found) => found,
+            None => return Polygon { vertices: vec![] },
+        };
+        if distance <= 0.0 {
+            return self.clone();
+        }
+
+        let n = vertices.len();
+        let mut grown = Vec::with_capacity(n * 2);
+        for i in 0..n {
+            let v = vertices[i];
+            // normals[i] belongs to the edge leaving vertex i
+            let n_in = normals[(i + n - 1) % n];
+            let n_out = normals[i];
+
+            let miter = (n_in + n_out).normalize_or_zero();
+            let miter_scale = if miter == Vec2::ZERO {
+                f32::INFINITY
+            } else {
+                1.0 / miter.dot(n_in)
+            };
+
+            match join {
+                Join::Miter if miter_scale <= MITER_LIMIT => {
+                    grown.push(v + miter * miter_scale * distance);
+                }
+                Join::Round => {
+                    let angle = n_in.perp_dot(n_out).atan2(n_in.dot(n_out));
+                    // fine enough that the facets don't show at any size
+                    // we draw outlines at
+                    let steps = (angle.abs() / (std::f32::consts::PI / 16.0))
+                        .ceil()
+                        .max(1.0);
+                    for step in 0..=steps as usize {
+                        let rotation = Vec2::from_angle(angle * step as f32 / steps);
+                        grown.push(v + rotation.rotate(n_in) * distance);
+                    }
+                }
+                Join::Miter | Join::Bevel => {
+                    grown.push(v + n_in * distance);
+                    grown.push(v + n_out * distance);
+                }
+            }
+        }
+
+        Polygon { vertices: grown }
+    }
+    // vertices without repeats, and the outward unit normal of the edge
+    // leaving each one; None if there's no area to have an outside of
+    fn outward_normals(&self) -> Option<(Vec<Vec2>, Vec<Vec2>)> {
+        let mut vertices = self.vertices.clone();
+        vertices.dedup_by(|a, b| a.distance_squared(*b) <= f32::EPSILON * f32::EPSILON);
+        while vertices.len() > 1
+            && vertices[0].distance_squared(vertices[vertices.len() - 1])
+                <= f32::EPSILON * f32::EPSILON
+        {
+            vertices.pop();
+        }
+
+        let n = vertices.len();
+        let twice_area: f32 = (0..n)
+            .map(|i| vertices[i].perp_dot(vertices[(i + 1) % n]))
+            .sum();
+        if n < 3 || twice_area == 0.0 {
+            return None;
+        }
+        // which way is out depends on the winding
+        let outward = twice_area.signum();
+
+        let normals = (0..n)
+            .map(|i| {
+                let d = (vertices[(i + 1) % n] - vertices[i]).normalize_or_zero();
+                Vec2::new(d.y, -d.x) * outward
+            })
+            .collect();
+        Some((vertices, normals))
+    }
+}
+
+/// How the corners of a grown polygon (and so an outline) are filled in,
+/// as in SVG's `stroke-linejoin`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Join {
+    /// Edges extended until they meet, unless that's further out than
+    /// [`MITER_LIMIT`], in which case the corner is beveled.
+    #[default]
+    Miter,
+    /// A circular arc around the corner.
+    Round,
+    /// Cut straight across.
+    Bevel,
+}
+
+/// How far a miter may reach past its corner, in multiples of the distance
+/// grown by. The same as SVG's default `stroke-miterlimit`.
+pub const MITER_LIMIT: f32 = 4.0;
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn polygon(vertices: &[[f32; 2]]) -> Polygon {
+        Polygon {
+            vertices: vertices.iter().map(|&v| Vec2::from(v)).collect(),
+        }
+    }
+
+    fn unit_square() -> Polygon {
+        Polygon::new_rect(Vec2::ZERO, Vec2::ONE)
+    }
+
+    // a plane along x = `x`, clipping away everything right of it
+    fn keep_left_of(polygon: &Polygon, x: f32) -> Polygon {
+        let plane = Plane {
+            normal: Vec2::X,
+            distance: x,
+        };
+        polygon.clip_against_plane(&plane, true)
+    }
+
+    #[test]
+    fn unit_square_measures() {
+        let square = unit_square();
+        assert_eq!(square.area(), 1.0);
+        assert_eq!(square.signed_area(), -1.0);
+        assert_eq!(square.perimeter(), 4.0);
+        assert_eq!(square.centroid(), Some(Vec2::splat(0.5)));
+        assert_eq!(square.bounds(), Some((Vec2::ZERO, Vec2::ONE)));
+        assert_eq!(square.winding(), Some(Winding::CounterClockwise));
+        assert!(square.is_convex());
+    }
+
+    #[test]
+    fn reversed_winding() {
+        let mut square = unit_square();
+        square.vertices.reverse();
+        assert_eq!(square.signed_area(), 1.0);
+        assert_eq!(square.winding(), Some(Winding::Clockwise));
+        assert!(square.is_convex());
+        assert!(square.contains(Vec2::splat(0.5)));
+        assert_eq!(square.centroid(), Some(Vec2::splat(0.5)));
+    }
+
+    #[test]
+    fn containment_includes_edges() {
+        let square = unit_square();
+        for inside in [[0.5, 0.5], [0.0, 0.0], [1.0, 0.3], [0.2, 1.0]] {
+            assert!(square.contains(Vec2::from(inside)), "{:?}", inside);
+        }
+        for outside in [[-0.01, 0.5], [0.5, 1.01], [2.0, 2.0]] {
+            assert!(!square.contains(Vec2::from(outside)), "{:?}", outside);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let empty = Polygon { vertices: vec![] };
+        assert_eq!(empty.area(), 0.0);
+        assert_eq!(empty.perimeter(), 0.0);
+        assert_eq!(empty.centroid(), None);
+        assert_eq!(empty.bounds(), None);
+        assert_eq!(empty.winding(), None);
+        assert!(!empty.is_convex());
+        assert!(!empty.contains(Vec2::ZERO));
+    }
+
+    #[test]
+    fn single_point() {
+        let point = polygon(&[[0.25, 0.75]]);
+        assert_eq!(point.area(), 0.0);
+        assert_eq!(point.perimeter(), 0.0);
+        assert_eq!(point.centroid(), Some(Vec2::new(0.25, 0.75)));
+        assert_eq!(
+            point.bounds(),
+            Some((Vec2::new(0.25, 0.75), Vec2::new(0.25, 0.75)))
+        );
+        assert_eq!(point.winding(), None);
+        assert!(!point.contains(Vec2::new(0.25, 0.75)));
+    }
+
+    #[test]
+    fn segment_and_collinear() {
+        let segment = polygon(&[[0.0, 0.0], [1.0, 0.0]]);
+        assert_eq!(segment.perimeter(), 2.0);
+        assert_eq!(segment.centroid(), Some(Vec2::new(0.5, 0.0)));
+        assert_eq!(segment.winding(), None);
+
+        let collinear = polygon(&[[0.0, 0.0], [0.5, 0.5], [1.0, 1.0]]);
+        assert_eq!(collinear.area(), 0.0);
+        assert_eq!(collinear.centroid(), Some(Vec2::splat(0.5)));
+        assert_eq!(collinear.winding(), None);
+        assert!(!collinear.is_convex());
+        assert!(!collinear.contains(Vec2::splat(0.5)));
+    }
+
+    #[test]
+    fn repeated_and_straight_vertices_stay_convex() {
+        // what clipping leaves when a plane passes exactly through corners
+        let square = polygon(&[
+            [0.0, 0.0],
+            [0.0, 0.0],
+            [0.0, 0.5],
+            [0.0, 1.0],
+            [1.0, 1.0],
+            [1.0, 0.0],
+        ]);
+        assert_eq!(square.area(), 1.0);
+        assert_eq!(square.centroid(), Some(Vec2::splat(0.5)));
+        assert!(square.is_convex());
+        assert!(square.contains(Vec2::new(0.0, 0.5)));
+    }
+
+    #[test]
+    fn aspect_ratio() {
+        assert_eq!(unit_square().aspect_ratio(), 1.0);
+        let long = Polygon::new_rect(Vec2::ZERO, Vec2::new(4.0, 1.0));
+        assert_eq!(long.aspect_ratio(), 0.25);
+        let equilateral = polygon(&[[0.0, 0.0], [0.5, 0.75f32.sqrt()], [1.0, 0.0]]);
+        assert!((equilateral.aspect_ratio() - 0.75f32.sqrt()).abs() < 1e-6);
+
+        let sliver = keep_left_of(&unit_square(), 1e-5);
+        assert!(sliver.aspect_ratio() < 2e-5);
+        assert_eq!(polygon(&[]).aspect_ratio(), 0.0);
+        assert_eq!(polygon(&[[0.0, 0.0], [1.0, 1.0]]).aspect_ratio(), 0.0);
+    }
+
+    #[test]
+    fn span_along_an_edge() {
+        let square = unit_square();
+        let left = Plane::through(Vec2::ZERO, Vec2::X);
+        let (lo, hi) = square.span_along(&left).unwrap();
+        assert_eq!((hi - lo).abs(), 1.0);
+
+        // touching at a corner, or not at all, isn't running along
+        let corner = Plane::through(Vec2::ZERO, Vec2::new(1.0, 1.0).normalize());
+        assert_eq!(square.span_along(&corner), None);
+        assert_eq!(
+            square.span_along(&Plane::through(Vec2::splat(-1.0), Vec2::X)),
+            None
+        );
+
+        // a clipped edge still lines up with the plane it was clipped by
+        let cut = Plane::through(Vec2::splat(0.5), Vec2::new(0.6, 0.8));
+        let le = square.clip_against_plane(&cut, true);
+        let gt = square.clip_against_plane(&cut, false);
+        let (a, b) = (le.span_along(&cut).unwrap(), gt.span_along(&cut).unwrap());
+        assert!((a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn concave_is_not_convex() {
+        let arrow = polygon(&[[0.0, 0.0], [0.5, 0.2], [1.0, 0.0], [0.5, 1.0]]);
+        assert!(arrow.winding().is_some());
+        assert!(!arrow.is_convex());
+    }
+
+    #[test]
+    fn clipping_keeps_winding() {
+        let square = unit_square();
+        let planes = [
+            Plane {
+                normal: Vec2::new(1.0, 1.0).normalize(),
+                distance: 0.9,
+            },
+            Plane {
+                normal: Vec2::new(-0.6, 0.8),
+                distance: 0.1,
+            },
+        ];
+        for plane in &planes {
+            for side in [false, true] {
+                let clipped = square.clip_against_plane(plane, side);
+                assert_eq!(clipped.winding(), Some(Winding::CounterClockwise));
+                assert!(clipped.is_convex());
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_halves_add_up() {
+        let square = unit_square();
+        let plane = Plane {
+            normal: Vec2::new(0.6, 0.8),
+            distance: 0.55,
+        };
+        let le = square.clip_against_plane(&plane, true);
+        let gt = square.clip_against_plane(&plane, false);
+        assert!((le.area() + gt.area() - 1.0).abs() < 1e-6);
+
+        // the centroid of the whole is the area-weighted mean of its parts
+        let combined = (le.centroid().unwrap() * le.area() + gt.centroid().unwrap() * gt.area())
+            / (le.area() + gt.area());
+        assert!(combined.distance(Vec2::splat(0.5)) < 1e-5);
+    }
+
+    #[test]
+    fn near_degenerate_sliver_from_clipping() {
+        // a plane just inside the left edge leaves a sliver only a couple of
+        // hundred f32 steps wide next to 1.0
+        let sliver = keep_left_of(&unit_square(), 1e-5);
+        assert_eq!(sliver.vertices.len(), 4);
+        assert!(sliver.area() > 0.0 && sliver.area() < 2e-5);
+        assert!((sliver.perimeter() - 2.0).abs() < 1e-4);
+
+        let (min, max) = sliver.bounds().unwrap();
+        assert_eq!(min, Vec2::ZERO);
+        assert!(max.x <= 2e-5 && max.y == 1.0);
+
+        let centroid = sliver.centroid().unwrap();
+        assert!(centroid.cmpge(min - 1e-6).all() && centroid.cmple(max + 1e-6).all());
+        // the cut isn't exactly square at that width, so neither is this
+        assert!((centroid.y - 0.5).abs() < 1e-2);
+    }
+
+    #[test]
+    fn slivers_at_an_edge_are_dropped() {
+        // a plane on the edge, or closer to it than EPSILON, would leave
+        // zero width behind
+        for x in [0.0, EPSILON * 0.5, -EPSILON * 0.5] {
+            let sliver = keep_left_of(&unit_square(), x);
+            assert!(sliver.vertices.is_empty(), "{:?}", sliver);
+
+            // and the other side keeps its corners where they were
+            let plane = Plane {
+                normal: Vec2::X,
+                distance: x,
+            };
+            let rest = unit_square().clip_against_plane(&plane, false);
+            assert_eq!(rest.vertices, unit_square().vertices);
+        }
+    }
+
+    #[test]
+    fn tiny_triangle_far_from_origin() {
+        // small enough that a naive centroid loses it to rounding
+        let offset = Vec2::splat(1000.0);
+        let tiny = Polygon {
+            vertices: [[0.0, 0.0], [0.0, 1e-3], [1e-3, 0.0]]
+                .iter()
+                .map(|&v| offset + Vec2::from(v))
+                .collect(),
+        };
+        assert_eq!(tiny.winding(), Some(Winding::CounterClockwise));
+        assert!(tiny.is_convex());
+
+        let centroid = tiny.centroid().unwrap();
+        let expected = offset + Vec2::splat(1e-3 / 3.0);
+        assert!(centroid.distance(expected) < 1e-4);
+        assert!(tiny.contains(centroid));
+    }
+
+    #[test]
+    fn repeated_clipping_stays_consistent() {
+        // whittle a square down through many cuts that nearly graze each
+        // other's corners
+        let mut region = unit_square();
+        for i in 0..40 {
+            let angle = i as f32 * 2.4;
+            let normal = Vec2::from_angle(angle);
+            let (lo, hi) = region
+                .vertices
+                .iter()
+                .map(|v| v.dot(normal))
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), d| {
+                    (lo.min(d), hi.max(d))
+                });
+            let plane = Plane {
+                normal,
+                distance: hi - (hi - lo) * 0.02,
+            };
+            let clipped = region.clip_against_plane(&plane, true);
+            if clipped.vertices.len() < 3 {
+                break;
+            }
+            assert!(clipped.area() <= region.area() + 1e-6);
+            if clipped.winding().is_some() {
+                assert_eq!(clipped.winding(), Some(Winding::CounterClockwise));
+                assert!(clipped.is_convex());
+                assert!(clipped.contains(clipped.centroid().unwrap()));
+            }
+            region = clipped;
+        }
+    }
+
+    // the kinds of cut that trip clipping up: anywhere, through a corner,
+    // corner to corner, and almost along an edge
+    fn random_plane(rng: &mut ChaCha8Rng, region: &Polygon) -> Plane {
+        let n = region.vertices.len();
+        let vertex = |rng: &mut ChaCha8Rng| region.vertices[rng.gen_range(0..n)];
+        let through = |point: Vec2, normal: Vec2| Plane {
+            normal,
+            distance: point.dot(normal),
+        };
+
+        match rng.gen_range(0..4) {
+            0 => {
+                let normal = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU));
+                let (min, max) = region.bounds().unwrap();
+                let point = min + (max - min) * Vec2::new(rng.gen(), rng.gen());
+                through(point, normal)
+            }
+            1 => {
+                let normal = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU));
+                through(vertex(rng), normal)
+            }
+            2 => {
+                let (a, b) = (vertex(rng), vertex(rng));
+                let normal = (b - a).perp().try_normalize().unwrap_or(Vec2::X);
+                through(a, normal)
+            }
+            _ => {
+                let i = rng.gen_range(0..n);
+                let (a, b) = (region.vertices[i], region.vertices[(i + 1) % n]);
+                let along = (b - a).try_normalize().unwrap_or(Vec2::Y);
+                let normal = along
+                    .perp()
+                    .rotate(Vec2::from_angle(rng.gen_range(-1e-5..1e-5)));
+                let mut plane = through(a, normal);
+                plane.distance += rng.gen_range(-1e-6..1e-6);
+                plane
+            }
+        }
+    }
+
+    fn assert_valid_half(half: &Polygon, plane: &Plane, sign: f32) {
+        if half.vertices.is_empty() {
+            return;
+        }
+        assert!(half.vertices.len() >= 3, "{:?}", half);
+        assert_eq!(
+            half.winding(),
+            Some(Winding::CounterClockwise),
+            "{:?}",
+            half
+        );
+        assert!(half.is_convex(), "{:?}", half);
+        for &v in &half.vertices {
+            assert!(v.is_finite());
+            assert!(
+                plane.distance_to_point(v) * sign >= -1e-5,
+                "{:?} {:?}",
+                v,
+                plane
+            );
+        }
+    }
+
+    #[test]
+    fn random_planes_split_cleanly() {
+        let mut rng = ChaCha8Rng::seed_from_u64(18);
+        for _ in 0..5000 {
+            // a random convex region, carved out of the unit square
+            let mut region = unit_square();
+            for _ in 0..rng.gen_range(0..6) {
+                let plane = random_plane(&mut rng, &region);
+                let clipped = region.clip_against_plane(&plane, rng.gen());
+                if !clipped.vertices.is_empty() {
+                    region = clipped;
+                }
+            }
+
+            let plane = random_plane(&mut rng, &region);
+            let le = region.clip_against_plane(&plane, true);
+            let gt = region.clip_against_plane(&plane, false);
+            assert_valid_half(&le, &plane, -1.0);
+            assert_valid_half(&gt, &plane, 1.0);
+
+            // dropping a sliver can only lose a sliver's worth of area
+            let lost = region.area() - le.area() - gt.area();
+            assert!(lost.abs() < 1e-5, "{} {:?} {:?}", lost, region, plane);
+
+            let (min, max) = region.bounds().unwrap();
+            for v in le.vertices.iter().chain(&gt.vertices) {
+                assert!(v.cmpge(min - 1e-5).all() && v.cmple(max + 1e-5).all());
+            }
+        }
+    }
+
+    #[test]
+    fn thousands_of_splits_keep_the_area() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1018);
+        let mut regions = vec![unit_square()];
+        for _ in 0..5000 {
+            let region = regions.swap_remove(rng.gen_range(0..regions.len()));
+            let plane = random_plane(&mut rng, &region);
+            let le = region.clip_against_plane(&plane, true);
+            let gt = region.clip_against_plane(&plane, false);
+            assert_valid_half(&le, &plane, -1.0);
+            assert_valid_half(&gt, &plane, 1.0);
+            regions.extend(
+                [le, gt]
+                    .into_iter()
+                    .filter(|half| !half.vertices.is_empty()),
+            );
+        }
+
+        let total: f32 = regions.iter().map(Polygon::area).sum();
+        assert!((total - 1.0).abs() < 1e-3, "{}", total);
+    }
+
+    #[test]
+    fn degenerate_input_does_not_panic() {
+        let plane = Plane {
+            normal: Vec2::X,
+            distance: 0.5,
+        };
+        let odd_planes = [
+            plane.clone(),
+            Plane {
+                normal: Vec2::ZERO,
+                distance: 0.0,
+            },
+            Plane {
+                normal: Vec2::X,
+                distance: f32::NAN,
+            },
+        ];
+        let odd_polygons = [
+            polygon(&[]),
+            polygon(&[[0.2, 0.2]]),
+            polygon(&[[0.0, 0.0], [1.0, 1.0]]),
+            polygon(&[[0.0, 0.0], [0.5, 0.5], [1.0, 1.0]]),
+            polygon(&[[0.3, 0.3], [0.3, 0.3], [0.3, 0.3]]),
+        ];
+        for odd in &odd_polygons {
+            for plane in &odd_planes {
+                for side in [false, true] {
+                    assert!(odd.clip_against_plane(plane, side).vertices.is_empty());
+                }
+            }
+        }
+
+        // a plane that can't be told apart from no plane at all cuts nothing
+        for plane in &odd_planes[1..] {
+            let clipped = unit_square().clip_against_plane(plane, true);
+            assert_eq!(clipped.vertices, unit_square().vertices);
+        }
+
+        assert_eq!(plane.line_intersection((Vec2::ZERO, Vec2::ZERO)), None);
+        assert_eq!(
+            odd_planes[2].line_intersection((Vec2::ZERO, Vec2::ONE)),
+            None
+        );
+    }
+
+    #[test]
+    fn inset_shrinks_until_nothing_is_left() {
+        let square = unit_square();
+        let inset = square.inset(0.25);
+        assert!((inset.area() - 0.25).abs() < 1e-6);
+        assert_eq!(inset.bounds(), Some((Vec2::splat(0.25), Vec2::splat(0.75))));
+
+        assert!(square.inset(0.5).vertices.is_empty());
+        assert!(square.inset(2.0).vertices.is_empty());
+        assert_eq!(square.inset(0.0).vertices, square.vertices);
+    }
+
+    #[test]
+    fn sharp_miters_fall_back_to_bevels() {
+        // right angles at the origin and near (0, 1), and a 6 degree point
+        // at (10, 0)
+        let wedge = polygon(&[[0.0, 0.0], [10.0, 0.0], [0.0, 1.0]]);
+        let mitered = wedge.outset(0.1, Join::Miter);
+        assert_eq!(mitered.vertices.len(), 4);
+        assert!(mitered.vertices[0].distance(Vec2::splat(-0.1)) < 1e-6);
+        // the point is cut straight across, 0.1 out from it along each side
+        let (a, b) = (mitered.vertices[1], mitered.vertices[2]);
+        assert!(a.distance(Vec2::new(10.0, -0.1)) < 1e-6);
+        assert!((b.distance(Vec2::new(10.0, 0.0)) - 0.1).abs() < 1e-6);
+        assert!(mitered.is_convex());
+
+        // a bevel cuts every corner
+        assert_eq!(wedge.outset(0.1, Join::Bevel).vertices.len(), 6);
+    }
+
+    #[test]
+    fn round_joins_follow_an_arc() {
+        let square = unit_square();
+        let rounded = square.outset(0.1, Join::Round);
+        // a quarter turn at each corner, in sixteenths of half a turn, and
+        // both ends
+        assert_eq!(rounded.vertices.len(), 4 * 9);
+        for v in &rounded.vertices {
+            let nearest = square
+                .vertices
+                .iter()
+                .map(|c| c.distance(*v))
+                .fold(f32::INFINITY, f32::min);
+            assert!((nearest - 0.1).abs() < 1e-6, "{}", v);
+        }
+        assert!(rounded.is_convex());
+        assert!(rounded.area() > square.outset(0.1, Join::Bevel).area());
+        assert!(rounded.area() < square.outset(0.1, Join::Miter).area());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+use wrong_track::Bsp;
+
+use crate::generate::Rng;
+
+/// How much memory the undo stack may hold on to before it starts
+/// forgetting the oldest edits.
+pub const DEFAULT_BUDGET_BYTES: usize = 256 * 1024 * 1024;
+
+/// Everything an undo puts back.
+#[derive(Clone)]
+pub struct Snapshot<T> {
+    pub bsp: Bsp<T>,
+    /// Where the random sequence was, so making the same edits again after
+    /// an undo gives the same picture.
+    pub rng: Rng,
+    /// Pixels; changing it clips the picture differently.
+    pub document_size: [u32; 2],
+}
+
+impl<T> Snapshot<T> {
+    fn approx_size_bytes(&self) -> usize {
+        self.bsp.approx_size_bytes() + std::mem::size_of::<Self>()
+    }
+}
+
+/// Undo/redo for tree edits.
+///
+/// Every edit snapshots the whole tree. That's a memcpy-sized clone of the
+/// slotmap, which is cheap next to clipping the tree for a single frame, and
+/// it means every edit (however weird) can be undone without each one having
+/// to know how to reverse itself.
+pub struct History<T> {
+    undo: VecDeque<Snapshot<T>>,
+    redo: Vec<Snapshot<T>>,
+
+    used_bytes: usize,
+    budget_bytes: usize,
+}
+
+// which stack a snapshot just went onto
+#[derive(PartialEq)]
+enum Pushed {
+    Undo,
+    Redo,
+}
+
+impl<T: Clone> History<T> {
+    pub fn new(budget_bytes: usize) -> History<T> {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            used_bytes: 0,
+            budget_bytes,
+        }
+    }
+
+    /// Call right *before* changing `current`.
+    pub fn record(&mut self, current: Snapshot<T>) {
+        for old in self.redo.drain(..) {
+            self.used_bytes -= old.approx_size_bytes();
+        }
+
+        self.used_bytes += current.approx_size_bytes();
+        self.undo.push_back(current);
+        self.evict(Pushed::Undo);
+    }
+
+    /// Swaps `current` for the state before the last edit, if there is one.
+    pub fn undo(&mut self, current: Snapshot<T>) -> Option<Snapshot<T>> {
+        let prev = self.undo.pop_back()?;
+        self.used_bytes -= prev.approx_size_bytes();
+        self.used_bytes += current.approx_size_bytes();
+        self.redo.push(current);
+        self.evict(Pushed::Redo);
+        Some(prev)
+    }
+
+    /// Swaps `current` for the state after the last undo, if there is one.
+    pub fn redo(&mut self, current: Snapshot<T>) -> Option<Snapshot<T>> {
+        let next = self.redo.pop()?;
+        self.used_bytes -= next.approx_size_bytes();
+        self.used_bytes += current.approx_size_bytes();
+        self.undo.push_back(current);
+        self.evict(Pushed::Undo);
+        Some(next)
+    }
+
+    // forgets the oldest edits, then the furthest redos, until it's back
+    // under budget. The snapshot just pushed stays, even if it alone is over.
+    fn evict(&mut self, pushed: Pushed) {
+        while self.used_bytes > self.budget_bytes {
+            let spare_undo = self.undo.len() - (pushed == Pushed::Undo) as usize;
+            let spare_redo = self.redo.len() - (pushed == Pushed::Redo) as usize;
+            let dropped = if spare_undo > 0 {
+                self.undo.pop_front().unwrap()
+            } else if spare_redo > 0 {
+                self.redo.remove(0)
+            } else {
+                break;
+            };
+            self.used_bytes -= dropped.approx_size_bytes();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn snapshot(value: i32) -> Snapshot<i32> {
+        Snapshot {
+            bsp: Bsp::new(value),
+            rng: Rng::seed_from_u64(0),
+            document_size: [100, 100],
+        }
+    }
+
+    fn value(snapshot: &Snapshot<i32>) -> i32 {
+        *snapshot.bsp.leaf_value(snapshot.bsp.root_key()).unwrap()
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = History::new(DEFAULT_BUDGET_BYTES);
+        assert!(history.undo(snapshot(0)).is_none());
+
+        // edits 0 -> 1 -> 2
+        history.record(snapshot(0));
+        history.record(snapshot(1));
+        assert_eq!(history.undo_len(), 2);
+
+        let back = history.undo(snapshot(2)).unwrap();
+        assert_eq!(value(&back), 1);
+        let back = history.undo(back).unwrap();
+        assert_eq!(value(&back), 0);
+        assert!(!history.can_undo());
+        assert!(history.undo(snapshot(0)).is_none());
+
+        let forward = history.redo(back).unwrap();
+        assert_eq!(value(&forward), 1);
+        let forward = history.redo(forward).unwrap();
+        assert_eq!(value(&forward), 2);
+        assert!(!history.can_redo());
+        assert_eq!(history.undo_len(), 2);
+    }
+
+    #[test]
+    fn new_edits_drop_the_redos() {
+        let mut history = History::new(DEFAULT_BUDGET_BYTES);
+        history.record(snapshot(0));
+        history.record(snapshot(1));
+        let back = history.undo(snapshot(2)).unwrap();
+        assert!(history.can_redo());
+
+        // an edit from 1 to 3 instead
+        history.record(back);
+        assert!(!history.can_redo());
+        assert!(history.redo(snapshot(3)).is_none());
+        assert_eq!(value(&history.undo(snapshot(3)).unwrap()), 1);
+        assert_eq!(history.used_bytes(), 2 * snapshot(0).approx_size_bytes());
+    }
+
+    #[test]
+    fn budget_forgets_the_oldest() {
+        let size = snapshot(0).approx_size_bytes();
+        let mut history = History::new(2 * size + size / 2);
+
+        for i in 0..5 {
+            history.record(snapshot(i));
+            assert!(history.used_bytes() <= 2 * size + size / 2);
+        }
+        assert_eq!(history.undo_len(), 2);
+
+        // undoing and redoing moves snapshots around without going over
+        let back = history.undo(snapshot(5)).unwrap();
+        assert_eq!(value(&back), 4);
+        let back = history.undo(back).unwrap();
+        assert_eq!(value(&back), 3);
+        assert!(!history.can_undo());
+        assert!(history.used_bytes() <= 2 * size + size / 2);
+        let forward = history.redo(back).unwrap();
+        assert_eq!(value(&forward), 4);
+        assert!(history.used_bytes() <= 2 * size + size / 2);
+
+        // and a single edit over budget is still kept
+        let mut tiny = History::new(1);
+        tiny.record(snapshot(0));
+        assert_eq!(tiny.undo_len(), 1);
+        assert_eq!(value(&tiny.undo(snapshot(1)).unwrap()), 0);
+        assert!(tiny.can_redo());
+    }
+}
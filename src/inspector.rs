@@ -0,0 +1,187 @@
+use eframe::egui;
+use palette::Oklab;
+
+use wrong_track::{bsp, BspNode, RegionCache};
+
+/// The side panel section showing what's selected: a leaf's color, or a whole
+/// subtree, along with the splits above it. Clicking one of those splits
+/// selects everything under it instead.
+///
+/// A subtree's leaves are only counted again when the tree or the selection
+/// changes.
+#[derive(Default)]
+pub struct Inspector {
+    leaves: usize,
+    counted_for: Option<(u64, bsp::BspKey)>,
+}
+
+impl Inspector {
+    /// Shows `selected` in `revision` of the tree.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        bsp: &bsp::Bsp<Oklab>,
+        regions: &RegionCache,
+        revision: u64,
+        document_size: [u32; 2],
+        selected: &mut Option<bsp::BspKey>,
+    ) {
+        if let Some(key) = *selected {
+            if self.counted_for != Some((revision, key)) {
+                self.leaves = count_leaves(bsp, key);
+                self.counted_for = Some((revision, key));
+            }
+        }
+        inspector_ui(ui, bsp, regions, document_size, selected, self.leaves);
+    }
+}
+
+fn inspector_ui(
+    ui: &mut egui::Ui,
+    bsp: &bsp::Bsp<Oklab>,
+    regions: &RegionCache,
+    document_size: [u32; 2],
+    selected: &mut Option<bsp::BspKey>,
+    leaves: usize,
+) {
+    ui.heading("Inspector");
+
+    let (key, node) = match selected.and_then(|key| Some((key, bsp.get(key)?))) {
+        Some(found) => found,
+        None => {
+            ui.label("Nothing selected. Click a region with the Select tool.");
+            return;
+        }
+    };
+
+    egui::Grid::new("inspector").num_columns(2).show(ui, |ui| {
+        match node {
+            BspNode::Leaf(leaf) => {
+                let color = leaf.0;
+                let srgb = wrong_track::to_srgb(color);
+
+                ui.label("Leaf");
+                egui::widgets::color_picker::show_color(
+                    ui,
+                    crate::vec_to_color(color),
+                    egui::vec2(40.0, 16.0),
+                );
+                ui.end_row();
+
+                ui.label("Oklab");
+                ui.monospace(format!("{:.3} {:+.3} {:+.3}", color.l, color.a, color.b));
+                ui.end_row();
+
+                ui.label("sRGB");
+                ui.monospace(format!("{:.3} {:.3} {:.3}", srgb.red, srgb.green, srgb.blue));
+                ui.end_row();
+
+                ui.label("Hex");
+                ui.monospace(hex(color));
+                ui.end_row();
+
+                if !wrong_track::in_gamut(color) {
+                    ui.label("Gamut");
+                    ui.label("Outside sRGB; shown with less chroma");
+                    ui.end_row();
+                }
+            }
+            BspNode::Inode(inode) => {
+                ui.label("Subtree");
+                ui.label(format!("{} leaves", leaves));
+                ui.end_row();
+
+                ui.label("Split");
+                ui.monospace(plane_text(&inode.plane));
+                ui.end_row();
+            }
+        }
+
+        if let Some(region) = regions.region(key) {
+            // the canvas's long side is 1 unit
+            let [width, height] = document_size;
+            let pixels_per_unit = width.max(height) as f32;
+            let canvas = wrong_track::canvas_size(width, height);
+            let area = region.area();
+
+            ui.label("Area");
+            ui.monospace(format!(
+                "{:.3}% ({:.0} px²)",
+                100.0 * area / (canvas.x * canvas.y),
+                area * pixels_per_unit * pixels_per_unit
+            ));
+            ui.end_row();
+
+            ui.label("Vertices");
+            ui.monospace(region.vertices.len().to_string());
+            ui.end_row();
+
+            if let BspNode::Leaf(_) = node {
+                let neighbours = regions.neighbours(key);
+                let shared: f32 = neighbours.iter().map(|&(_, length)| length).sum();
+                ui.label("Neighbours");
+                ui.monospace(format!(
+                    "{} ({:.0} px shared)",
+                    neighbours.len(),
+                    shared * pixels_per_unit
+                ));
+                ui.end_row();
+            }
+        }
+
+        ui.label("Depth");
+        ui.monospace(bsp.depth(key).to_string());
+        ui.end_row();
+    });
+
+    ui.label("Splits above, from the root:");
+    let mut ancestors: Vec<bsp::BspKey> = bsp.ancestors(key).collect();
+    ancestors.reverse();
+    if ancestors.is_empty() {
+        ui.label("(none, this is the root)");
+    }
+
+    // which side of each split the path down takes
+    let path = ancestors.iter().skip(1).copied().chain(std::iter::once(key));
+    for (depth, (ancestor, child)) in ancestors.iter().copied().zip(path).enumerate() {
+        let inode = match bsp.get(ancestor) {
+            Some(BspNode::Inode(inode)) => inode,
+            _ => continue,
+        };
+        let side = if inode.le == child { "le" } else { "gt" };
+
+        let text = format!("{:>3} {} {}", depth, plane_text(&inode.plane), side);
+        if ui
+            .selectable_label(false, egui::RichText::new(text).monospace())
+            .on_hover_text("Select this split and everything under it")
+            .clicked()
+        {
+            *selected = Some(ancestor);
+        }
+    }
+}
+
+pub fn hex(color: Oklab) -> String {
+    let [r, g, b] = wrong_track::to_srgb8(wrong_track::to_srgb(color));
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+pub fn plane_text(plane: &wrong_track::Plane) -> String {
+    format!(
+        "n ({:+.3}, {:+.3}) d {:.3}",
+        plane.normal.x, plane.normal.y, plane.distance
+    )
+}
+
+fn count_leaves(bsp: &bsp::Bsp<Oklab>, key: bsp::BspKey) -> usize {
+    let mut count = 0;
+    let mut stack = vec![key];
+    while let Some(key) = stack.pop() {
+        match bsp.get(key) {
+            Some(BspNode::Inode(inode)) => stack.extend([inode.le, inode.gt]),
+            Some(BspNode::Leaf(_)) => count += 1,
+            None => {}
+        }
+    }
+    count
+}
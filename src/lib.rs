@@ -0,0 +1,43 @@
+//! Binary space partitions of the plane, and the tools to draw them.
+//!
+//! A [`Bsp`] starts out as a single region holding one value. Each split cuts
+//! a region in two along a line, so the tree always tiles the plane with
+//! convex [`Polygon`]s. Those can be pulled out with
+//! [`Bsp::visit_leaf_polygons`], or turned straight into pictures with
+//! [`save_svg`] and [`save_png`]. Pictures show the part of the plane given
+//! by [`canvas_size`]: the unit square for square ones. A [`Style`] adds
+//! outlines and gaps between the leaves. Oklab leaves that sRGB can't show
+//! are brought into gamut by [`to_srgb`] on the way.
+//!
+//! ```
+//! use glam::vec2;
+//! use palette::Srgb;
+//! use wrong_track::{Bsp, Polygon, Style};
+//!
+//! let mut bsp = Bsp::new(Srgb::new(1.0, 1.0, 1.0));
+//! bsp.split_at_point(vec2(0.5, 0.5), vec2(1.0, 0.0), Srgb::new(1.0, 0.0, 0.0));
+//! assert_eq!(bsp.get_at_point(vec2(0.75, 0.5)).red, 1.0);
+//!
+//! let regions = bsp.leaf_polygons(Polygon::new_rect(vec2(0.0, 0.0), vec2(1.0, 1.0)));
+//! assert_eq!(regions.len(), 2);
+//!
+//! let png = wrong_track::save_png(&bsp, 64, 64, &Style::default()).unwrap();
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod bsp;
+pub mod cache;
+pub mod color;
+pub mod export;
+pub mod geom;
+pub mod raster;
+pub mod style;
+
+pub use bsp::{Bsp, BspInode, BspKey, BspLeaf, BspNode, SplitConstraints};
+pub use cache::RegionCache;
+pub use color::{from_srgb, in_gamut, to_srgb, to_srgb8};
+pub use export::{save_svg, Fill};
+pub use geom::{canvas_size, Join, Plane, Polygon, Winding};
+pub use raster::{save_png, Rasterizer};
+pub use style::Style;
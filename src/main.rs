@@ -1,10 +1,24 @@
 use base64::Engine;
-use bsp::Polygon;
 use eframe::egui;
-use palette::{IntoColor, Mix, Oklab, Srgb};
+use palette::{Oklab, Srgb};
 use rand::prelude::*;
+use wrong_track::{
+    bsp, export, raster, Join, Plane, Polygon, RegionCache, SplitConstraints, Style,
+};
 
-mod bsp;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
+mod coloring;
+mod generate;
+mod history;
+mod inspector;
+mod mesh;
+mod overlay;
+mod palettes;
+mod project;
+mod split_edit;
+mod tree_view;
+mod view;
 
 #[cfg(target_arch = "wasm32")]
 fn main() {
@@ -34,6 +48,13 @@ fn main() {
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
+    use clap::Parser;
+
+    // with a subcommand we run headless and never open a window
+    if let Some(command) = cli::Cli::parse().command {
+        std::process::exit(cli::run(command));
+    }
+
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "wrong!track!",
@@ -42,353 +63,1359 @@ fn main() {
     );
 }
 
+/// What an edit to the tree touched, so caches can redo as little as possible.
+enum Changed {
+    /// Only leaf values.
+    Colors,
+    /// Everything below this node, but not the node's own region.
+    Subtree(bsp::BspKey),
+    /// The tree was replaced.
+    Everything,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum Tool {
     Split,
     Unsplit,
-    Paint
+    Paint,
+    Select,
 }
 struct MyEguiApp {
     // color
     bsp: bsp::Bsp<Oklab>,
+    history: history::History<Oklab>,
+    // everything below is derived from bsp and must be told about every
+    // edit to it, through tree_changed
+    regions: RegionCache,
+    leaf_mesh: mesh::LeafMesh,
+    revision: u64,
+
+    view: view::View,
+    show_splits: bool,
+    split_overlay: overlay::SplitOverlay,
+    show_gamut: bool,
+    gamut_overlay: overlay::GamutOverlay,
+    // pixels; what shape the canvas is, and how big exports come out
+    document_size: [u32; 2],
 
     normal_randomness: f32,
     color_randomness: f32,
     num_color_samples: usize,
-
-    drag_start_pos: egui::Pos2,
+    color_source: generate::ColorSource,
+    // what SMOOTH does
+    smooth_strength: f32,
+    smooth_iterations: usize,
+    // never empty; `palette` is the one new colors and RECOLOR pick from
+    palettes: Vec<palettes::Palette>,
+    palette: usize,
+    palette_mode: generate::PaletteMode,
+    #[cfg(not(target_arch = "wasm32"))]
+    palette_path: String,
+    // which of its many colorings RECOLOR picks
+    recolor_seed: u64,
+    // new regions avoid their neighbours' colors, so a RECOLOR stays put
+    keep_apart: bool,
+
+    // where a primary-button drag on the canvas started, in screen space
+    drag_start_pos: Option<egui::Pos2>,
+    // a paint stroke is one undo step, however many frames it lasts, and so
+    // is dragging the document size around
+    painting: bool,
+    resizing: bool,
 
     override_color_enabled: bool,
     override_color: egui::epaint::Hsva,
 
+    // every random thing the app does draws from this
+    seed: u64,
+    seed_text: String,
+    rng: generate::Rng,
+
     tool: Tool,
+    // a leaf, or an inode standing for its whole subtree
+    selected: Option<bsp::BspKey>,
+    inspector: inspector::Inspector,
+    split_editor: split_edit::SplitEditor,
+    show_tree: bool,
+    tree_view: tree_view::TreeView,
+
+    style: Style,
+    // PNGs come out at the document size times this
+    png_scale: f32,
+
+    // areas in canvas units squared
+    split_constraints: SplitConstraints,
+    auto_prune: bool,
+    // canvas units squared, like everything else kept relative to the canvas
+    prune_min_area: f32,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    project_path: String,
+    status: Option<String>,
 }
 
 impl MyEguiApp {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
         // Restore app state using cc.storage (requires the "persistence" feature).
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
 
         let bsp = bsp::Bsp::new(Oklab::new(1.0, 0.0, 0.0));
+        let seed = thread_rng().gen();
+        let document_size = project::default_document_size();
+        let canvas = canvas_size(document_size);
 
         MyEguiApp {
+            regions: RegionCache::new(&bsp, Polygon::new_rect(glam::Vec2::ZERO, canvas)),
+            leaf_mesh: mesh::LeafMesh::new(cc.gl.as_deref()),
+            revision: 0,
+
+            view: view::View::fit(canvas),
+            show_splits: false,
+            split_overlay: overlay::SplitOverlay::default(),
+            show_gamut: false,
+            gamut_overlay: overlay::GamutOverlay::default(),
+            document_size,
             bsp,
+            history: history::History::new(history::DEFAULT_BUDGET_BYTES),
 
             normal_randomness: 0.5,
             color_randomness: 0.5,
             num_color_samples: 3,
+            color_source: generate::ColorSource::default(),
+            smooth_strength: 0.5,
+            smooth_iterations: 4,
+            palettes: palettes::Palette::builtin(),
+            palette: 0,
+            palette_mode: generate::PaletteMode::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            palette_path: String::new(),
+            recolor_seed: 0,
+            keep_apart: false,
             override_color_enabled: false,
             override_color: egui::epaint::Hsva::default(),
 
-            drag_start_pos: egui::Pos2::ZERO,
+            seed,
+            seed_text: seed.to_string(),
+            rng: generate::Rng::seed_from_u64(seed),
+
+            drag_start_pos: None,
+            painting: false,
+            resizing: false,
 
             tool: Tool::Split,
+            selected: None,
+            inspector: inspector::Inspector::default(),
+            split_editor: split_edit::SplitEditor::default(),
+            show_tree: true,
+            tree_view: tree_view::TreeView::default(),
+
+            style: Style::default(),
+            png_scale: 1.0,
+
+            split_constraints: SplitConstraints::default(),
+            auto_prune: false,
+            prune_min_area: 0.0,
+
+            #[cfg(not(target_arch = "wasm32"))]
+            project_path: format!("untitled.{}", project::EXTENSION),
+            status: None,
+        }
+    }
+
+    fn tree_changed(&mut self, changed: Changed) {
+        self.leaf_mesh.changed(&changed);
+        match changed {
+            Changed::Colors => {}
+            Changed::Subtree(key) => self.regions.update_subtree(&self.bsp, key),
+            Changed::Everything => self.regions.rebuild(&self.bsp),
+        }
+        self.revision += 1;
+
+        if let Some(key) = self.selected {
+            if self.bsp.get(key).is_none() {
+                self.selected = None;
+            }
+        }
+    }
+
+    fn canvas(&self) -> glam::Vec2 {
+        canvas_size(self.document_size)
+    }
+
+    fn set_document_size(&mut self, size: [u32; 2]) {
+        if size == self.document_size {
+            return;
+        }
+        self.document_size = size;
+
+        let canvas = self.canvas();
+        self.regions
+            .set_bounds(&self.bsp, Polygon::new_rect(glam::Vec2::ZERO, canvas));
+        self.leaf_mesh.changed(&Changed::Everything);
+        self.revision += 1;
+        self.view = view::View::fit(canvas);
+    }
+
+    fn apply_tree_action(&mut self, action: tree_view::TreeAction) {
+        match action {
+            tree_view::TreeAction::Collapse(key) => {
+                if let Some(bsp::BspNode::Inode(_)) = self.bsp.get(key) {
+                    self.record();
+                    self.bsp.collapse(key);
+                    self.tree_changed(Changed::Subtree(key));
+                }
+            }
+            tree_view::TreeAction::Delete(key) => {
+                if self.bsp.parent(key).is_some() {
+                    self.record();
+                    if let Some(parent) = self.bsp.unsplit(key) {
+                        self.tree_changed(Changed::Subtree(parent));
+                    }
+                }
+            }
+        }
+    }
+
+    fn to_project(&self) -> project::Project {
+        let oc = self.override_color;
+        project::Project {
+            settings: project::Settings {
+                normal_randomness: self.normal_randomness,
+                color_randomness: self.color_randomness,
+                num_color_samples: self.num_color_samples,
+                color_source: self.color_source,
+                override_color_enabled: self.override_color_enabled,
+                override_color: [oc.h, oc.s, oc.v, oc.a],
+                seed: self.seed,
+                rng_position: self.rng.get_word_pos() as u64,
+                document_size: self.document_size,
+                style: self.style.clone(),
+                split_constraints: self.split_constraints,
+                auto_prune: self.auto_prune,
+                prune_min_area: self.prune_min_area,
+                palette: self.palettes[self.palette].clone(),
+                palettes: self.palettes.clone(),
+                palette_mode: self.palette_mode,
+                recolor_seed: self.recolor_seed,
+                keep_apart: self.keep_apart,
+            },
+            bsp: self.bsp.clone(),
+        }
+    }
+
+    fn apply_project(&mut self, project: project::Project) {
+        let settings = project.settings;
+        let [h, s, v, a] = settings.override_color;
+
+        self.record();
+        self.bsp = project.bsp;
+        self.tree_changed(Changed::Everything);
+        self.normal_randomness = settings.normal_randomness;
+        self.color_randomness = settings.color_randomness;
+        self.num_color_samples = settings.num_color_samples;
+        self.color_source = settings.color_source;
+        self.override_color_enabled = settings.override_color_enabled;
+        self.override_color = egui::epaint::Hsva::new(h, s, v, a);
+        self.reseed(settings.seed);
+        self.rng.set_word_pos(settings.rng_position.into());
+        self.set_document_size(settings.document_size);
+        self.style = settings.style;
+        self.split_constraints = settings.split_constraints;
+        self.auto_prune = settings.auto_prune;
+        self.prune_min_area = settings.prune_min_area;
+        self.palettes = settings.palettes;
+        self.palette = match self.palettes.iter().position(|p| *p == settings.palette) {
+            Some(i) => i,
+            None => {
+                self.palettes.push(settings.palette);
+                self.palettes.len() - 1
+            }
+        };
+        self.palette_mode = settings.palette_mode;
+        self.recolor_seed = settings.recolor_seed;
+        self.keep_apart = settings.keep_apart;
+    }
+
+    /// Restarts the random sequence. From here on, the same edits give the
+    /// same picture.
+    fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.seed_text = seed.to_string();
+        self.rng = generate::Rng::seed_from_u64(seed);
+    }
+
+    fn seed_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let label = ui.label("Seed").id;
+            let response = ui
+                .add(egui::TextEdit::singleline(&mut self.seed_text).desired_width(180.0))
+                .labelled_by(label)
+                .on_hover_text("Entering a seed, even the same one, restarts the random sequence");
+            if response.lost_focus() {
+                match self.seed_text.trim().parse() {
+                    Ok(seed) => self.reseed(seed),
+                    Err(_) => self.seed_text = self.seed.to_string(),
+                }
+            }
+            if ui.button("New seed").clicked() {
+                self.reseed(thread_rng().gen());
+            }
+        });
+    }
+
+    fn load_project_bytes(&mut self, bytes: &[u8]) {
+        match project::Project::from_bytes(bytes) {
+            Ok(project) => {
+                self.apply_project(project);
+                self.status = Some("Project loaded.".to_owned());
+            }
+            Err(e) => self.status = Some(e.to_string()),
+        }
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = std::mem::take(&mut ctx.input_mut().raw.dropped_files);
+
+        for file in dropped {
+            // the web only gives us names, native only paths
+            let name = match &file.path {
+                Some(path) => path.to_string_lossy().into_owned(),
+                None => file.name.clone(),
+            };
+            let bytes = if let Some(bytes) = &file.bytes {
+                bytes.to_vec()
+            } else if let Some(path) = &file.path {
+                match std::fs::read(path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        self.status = Some(project::ProjectError::Io(e).to_string());
+                        continue;
+                    }
+                }
+            } else {
+                continue;
+            };
+
+            if palettes::is_palette_file(&name) {
+                self.import_palette(&name, &bytes);
+            } else {
+                self.load_project_bytes(&bytes);
+            }
+        }
+    }
+
+    fn handle_undo_shortcuts(&mut self, ctx: &egui::Context) {
+        const UNDO: egui::KeyboardShortcut =
+            egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+        const REDO: egui::KeyboardShortcut = egui::KeyboardShortcut::new(
+            egui::Modifiers::COMMAND.plus(egui::Modifiers::SHIFT),
+            egui::Key::Z,
+        );
+
+        // text fields have their own undo
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let (undo, redo) = {
+            let mut input = ctx.input_mut();
+            (input.consume_shortcut(&UNDO), input.consume_shortcut(&REDO))
+        };
+        if undo {
+            self.undo();
+        }
+        if redo {
+            self.redo();
+        }
+    }
+
+    fn snapshot(&self) -> history::Snapshot<Oklab> {
+        history::Snapshot {
+            bsp: self.bsp.clone(),
+            rng: self.rng.clone(),
+            document_size: self.document_size,
+        }
+    }
+    /// Call right *before* an edit, to make it undoable.
+    fn record(&mut self) {
+        let snapshot = self.snapshot();
+        self.history.record(snapshot);
+    }
+    fn restore(&mut self, snapshot: history::Snapshot<Oklab>) {
+        self.bsp = snapshot.bsp;
+        self.rng = snapshot.rng;
+        self.set_document_size(snapshot.document_size);
+        self.tree_changed(Changed::Everything);
+    }
+
+    fn undo(&mut self) {
+        if self.history.can_undo() {
+            let current = self.snapshot();
+            if let Some(prev) = self.history.undo(current) {
+                self.restore(prev);
+            }
+        }
+    }
+    fn redo(&mut self) {
+        if self.history.can_redo() {
+            let current = self.snapshot();
+            if let Some(next) = self.history.redo(current) {
+                self.restore(next);
+            }
+        }
+    }
+
+    fn history_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.history.can_undo(), egui::Button::new("Undo"))
+                .on_hover_text("Ctrl+Z")
+                .clicked()
+            {
+                self.undo();
+            }
+            if ui
+                .add_enabled(self.history.can_redo(), egui::Button::new("Redo"))
+                .on_hover_text("Ctrl+Shift+Z")
+                .clicked()
+            {
+                self.redo();
+            }
+            ui.monospace(format!(
+                "{} steps, {:.1} MiB",
+                self.history.undo_len(),
+                self.history.used_bytes() as f32 / (1024.0 * 1024.0)
+            ));
+        });
+    }
+
+    fn export_ui(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Export SVG").clicked() {
+            let [width, height] = self.document_size;
+            let url = to_data_url(
+                export::save_svg(&self.bsp, width, height, &self.style),
+                "image/svg+xml",
+            );
+            open_url_new_tab(ui.ctx(), &url);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Export PNG").clicked() {
+                let [width, height] = self.png_size();
+                match raster::save_png(&self.bsp, width, height, &self.style) {
+                    Ok(png) => {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        {
+                            let path =
+                                std::path::Path::new(&self.project_path).with_extension("png");
+                            self.status = Some(match std::fs::write(&path, png) {
+                                Ok(()) => format!("Wrote {}.", path.display()),
+                                Err(e) => format!("could not write PNG: {}", e),
+                            });
+                        }
+                        #[cfg(target_arch = "wasm32")]
+                        wasm::save_data_url(
+                            to_data_url(png, "image/png"),
+                            "download.png".to_owned(),
+                        );
+                    }
+                    Err(e) => self.status = Some(format!("could not make PNG: {}", e)),
+                }
+            }
+
+            ui.add(
+                egui::DragValue::new(&mut self.png_scale)
+                    .clamp_range(0.1..=8.0)
+                    .speed(0.01)
+                    .suffix("x"),
+            );
+            let [width, height] = self.png_size();
+            ui.label(format!("{}x{}", width, height));
+        });
+    }
+
+    fn png_size(&self) -> [u32; 2] {
+        self.document_size.map(|d| {
+            ((d as f32 * self.png_scale).round() as u32).clamp(1, project::MAX_DOCUMENT_SIZE)
+        })
+    }
+
+    fn style_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Style");
+
+        // shown in document pixels, kept relative to the long side
+        let pixels_per_unit = self.document_size[0].max(self.document_size[1]) as f32;
+        let max = project::MAX_STYLE_LENGTH * pixels_per_unit;
+        let length_ui = |ui: &mut egui::Ui, length: &mut f32| {
+            let mut px = *length * pixels_per_unit;
+            let response = ui.add(
+                egui::DragValue::new(&mut px)
+                    .clamp_range(0.0..=max)
+                    .speed(0.1)
+                    .suffix(" px"),
+            );
+            if response.changed() {
+                *length = px / pixels_per_unit;
+            }
+        };
+        let color_ui = |ui: &mut egui::Ui, color: &mut palette::Srgb| {
+            let mut rgb = color.into_format::<u8>().into_components().into();
+            if ui.color_edit_button_srgb(&mut rgb).changed() {
+                *color = palette::Srgb::from_components(rgb.into()).into_format();
+            }
+        };
+
+        egui::Grid::new("style").num_columns(2).show(ui, |ui| {
+            ui.label("Outline");
+            ui.horizontal(|ui| {
+                length_ui(ui, &mut self.style.outline_width);
+                color_ui(ui, &mut self.style.outline_color);
+                egui::ComboBox::from_id_source("outline join")
+                    .selected_text(format!("{:?}", self.style.outline_join))
+                    .show_ui(ui, |ui| {
+                        for join in [Join::Miter, Join::Round, Join::Bevel] {
+                            ui.selectable_value(
+                                &mut self.style.outline_join,
+                                join,
+                                format!("{:?}", join),
+                            );
+                        }
+                    });
+            });
+            ui.end_row();
+
+            ui.label("Gutter").on_hover_text(
+                "How far each region is inset; the gap between neighbours is twice this",
+            );
+            ui.horizontal(|ui| {
+                length_ui(ui, &mut self.style.gutter);
+
+                let mut filled = self.style.gutter_color.is_some();
+                ui.checkbox(&mut filled, "Fill");
+                match (filled, &mut self.style.gutter_color) {
+                    (true, Some(color)) => color_ui(ui, color),
+                    (true, gutter_color @ None) => {
+                        *gutter_color = Some(palette::Srgb::new(1.0, 1.0, 1.0));
+                    }
+                    (false, gutter_color) => *gutter_color = None,
+                }
+            });
+            ui.end_row();
+        });
+    }
+
+    fn constraints_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Split limits");
+        ui.label(
+            "What a split must leave on either side. \
+             Random splits that don't fit are rolled again.",
+        );
+
+        // shown in square document pixels
+        let pixels_per_unit = self.document_size[0].max(self.document_size[1]) as f32;
+        let px_per_area = pixels_per_unit * pixels_per_unit;
+        let constraints = &mut self.split_constraints;
+        egui::Grid::new("split limits").num_columns(2).show(ui, |ui| {
+            let label = ui.label("Min. area").id;
+            let mut px = constraints.min_area * px_per_area;
+            let response = ui
+                .add(
+                    egui::DragValue::new(&mut px)
+                        .clamp_range(0.0..=px_per_area)
+                        .speed(10.0)
+                        .suffix(" px²"),
+                )
+                .labelled_by(label);
+            if response.changed() {
+                constraints.min_area = px / px_per_area;
+            }
+            ui.end_row();
+
+            let label = ui.label("Min. aspect ratio").id;
+            ui.add(egui::Slider::new(&mut constraints.min_aspect_ratio, 0.0..=1.0))
+                .labelled_by(label)
+                .on_hover_text("Narrowest width over length: 1 allows only squares");
+            ui.end_row();
+        });
+    }
+
+    // a split from the Split tool, unless it breaks the split limits, in
+    // which case the status line says why
+    fn split(&mut self, point: glam::Vec2, normal: glam::Vec2, color: Oklab) {
+        let leaf = self.bsp.leaf_index_for_point(point);
+        let fits = match self.regions.region(leaf) {
+            Some(region) => self
+                .split_constraints
+                .check(region, &Plane::through(point, normal)),
+            None => return,
+        };
+        if let Err(e) = fits {
+            self.status = Some(e.to_string());
+            return;
+        }
+
+        self.record();
+        let key = self.bsp.split_at_point(point, normal, color);
+        self.prune_split(key);
+        self.split_done(key);
+    }
+
+    // catches up with a split at `key`, keeping neighbours apart if asked to
+    fn split_done(&mut self, key: bsp::BspKey) {
+        self.tree_changed(Changed::Subtree(key));
+        if self.keep_apart {
+            coloring::keep_apart(
+                &mut self.bsp,
+                &self.regions,
+                key,
+                &self.palettes[self.palette].oklab(),
+            );
+            self.tree_changed(Changed::Colors);
+        }
+    }
+
+    fn prune_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Pruning");
+
+        // shown in square document pixels
+        let pixels_per_unit = self.document_size[0].max(self.document_size[1]) as f32;
+        let px_per_area = pixels_per_unit * pixels_per_unit;
+        ui.horizontal(|ui| {
+            let label = ui.label("Min. area").id;
+            let mut px = self.prune_min_area * px_per_area;
+            let response = ui
+                .add(
+                    egui::DragValue::new(&mut px)
+                        .clamp_range(0.0..=px_per_area)
+                        .speed(0.01)
+                        .suffix(" px²"),
+                )
+                .labelled_by(label)
+                .on_hover_text(
+                    "Regions smaller than this are merged into their neighbour. \
+                     At 0 the picture stays exactly the same.",
+                );
+            if response.changed() {
+                self.prune_min_area = px / px_per_area;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("Prune")
+                .on_hover_text("Remove splits that don't divide anything, and regions below the min. area")
+                .clicked()
+            {
+                // an undo step only if there was something to prune
+                let before = self.snapshot();
+                let root = self.bsp.root_key();
+                let removed = self
+                    .bsp
+                    .prune(root, self.regions.bounds().clone(), self.prune_min_area);
+                if removed > 0 {
+                    self.history.record(before);
+                    self.tree_changed(Changed::Subtree(root));
+                }
+                self.status = Some(format!("Pruned {} nodes.", removed));
+            }
+            ui.checkbox(&mut self.auto_prune, "After every split");
+        });
+    }
+
+    // tidies up after a split at `key`, if pruning after every split is on
+    fn prune_split(&mut self, key: bsp::BspKey) {
+        if let (true, Some(region)) = (self.auto_prune, self.regions.region(key)) {
+            self.bsp.prune(key, region.clone(), self.prune_min_area);
+        }
+    }
+
+    fn palette_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Palette");
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("palette")
+                .selected_text(&self.palettes[self.palette].name)
+                .show_ui(ui, |ui| {
+                    for (i, palette) in self.palettes.iter().enumerate() {
+                        ui.selectable_value(&mut self.palette, i, &palette.name);
+                    }
+                });
+            if ui.button("Copy").clicked() {
+                let mut copy = self.palettes[self.palette].clone();
+                copy.name = format!("{} copy", copy.name);
+                self.palettes.push(copy);
+                self.palette = self.palettes.len() - 1;
+            }
+            if ui
+                .add_enabled(self.palettes.len() > 1, egui::Button::new("Delete"))
+                .clicked()
+            {
+                self.palettes.remove(self.palette);
+                self.palette = self.palette.min(self.palettes.len() - 1);
+            }
+        });
+
+        let current = &mut self.palettes[self.palette];
+        ui.horizontal(|ui| {
+            let label = ui.label("Name").id;
+            ui.text_edit_singleline(&mut current.name)
+                .labelled_by(label);
+        });
+        egui::CollapsingHeader::new(format!("{} colors", current.colors.len()))
+            .id_source("palette colors")
+            .show(ui, |ui| {
+                let mut removed = None;
+                egui::Grid::new("palette colors")
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        for i in 0..current.colors.len() {
+                            let color = &mut current.colors[i];
+                            let mut rgb = color.into_format::<u8>().into_components().into();
+                            if ui.color_edit_button_srgb(&mut rgb).changed() {
+                                *color = Srgb::from_components(rgb.into()).into_format();
+                            }
+
+                            let mut weight = current.weight(i);
+                            let response = ui
+                                .add(
+                                    egui::DragValue::new(&mut weight)
+                                        .clamp_range(0.0..=palettes::MAX_WEIGHT)
+                                        .speed(0.05)
+                                        .prefix("weight "),
+                                )
+                                .on_hover_text("How likely this color is next to the others");
+                            if response.changed() {
+                                current.weights.resize(current.colors.len(), 1.0);
+                                current.weights[i] = weight;
+                            }
+
+                            if ui.small_button("🗙").on_hover_text("Remove").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                if let Some(i) = removed {
+                    current.remove(i);
+                }
+                if ui.button("Add color").clicked() {
+                    let last = current.colors.last().copied();
+                    current.push(last.unwrap_or(Srgb::new(1.0, 1.0, 1.0)));
+                }
+            });
+
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.palette_path).hint_text("palette.gpl"));
+            if ui.button("Import").clicked() {
+                let path = self.palette_path.clone();
+                match std::fs::read(&path) {
+                    Ok(bytes) => self.import_palette(&path, &bytes),
+                    Err(e) => self.status = Some(format!("could not read palette: {}", e)),
+                }
+            }
+        });
+        ui.label("...or drop a .gpl, .ase or list of hex colors onto the window to import it.");
+
+        ui.horizontal(|ui| {
+            ui.label("New colors");
+            ui.radio_value(&mut self.palette_mode, generate::PaletteMode::Off, "Any")
+                .on_hover_text("Whatever the color settings come up with");
+            ui.radio_value(&mut self.palette_mode, generate::PaletteMode::Pick, "Pick")
+                .on_hover_text("A palette color drawn at random, going by the weights");
+            ui.radio_value(&mut self.palette_mode, generate::PaletteMode::Snap, "Snap")
+                .on_hover_text("The nearest palette color to what the color settings come up with; heavier colors reach further");
+        });
+    }
+
+    fn import_palette(&mut self, file_name: &str, bytes: &[u8]) {
+        match palettes::Palette::import(file_name, bytes) {
+            Ok(palette) => {
+                self.status = Some(format!(
+                    "Imported {} with {} colors.",
+                    palette.name,
+                    palette.colors.len()
+                ));
+                self.palettes.push(palette);
+                self.palette = self.palettes.len() - 1;
+            }
+            Err(e) => self.status = Some(e.to_string()),
+        }
+    }
+
+    fn coloring_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Map coloring");
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("RECOLOR")
+                .on_hover_text("Color every region from the palette so that no neighbours match")
+                .clicked()
+            {
+                self.recolor();
+            }
+            let label = ui.label("Seed").id;
+            ui.add(egui::DragValue::new(&mut self.recolor_seed))
+                .labelled_by(label)
+                .on_hover_text("Each seed colors the regions differently");
+            if ui.button("Another").clicked() {
+                self.recolor_seed = self.recolor_seed.wrapping_add(1);
+                self.recolor();
+            }
+        });
+
+        ui.checkbox(&mut self.keep_apart, "Keep neighbours apart")
+            .on_hover_text("New regions take the nearest palette color none of their neighbours have");
+    }
+
+    fn recolor(&mut self) {
+        self.record();
+        let clashes = coloring::recolor(
+            &mut self.bsp,
+            &self.regions,
+            &self.palettes[self.palette].oklab(),
+            self.recolor_seed,
+        );
+        self.tree_changed(Changed::Colors);
+        if clashes > 0 {
+            self.status = Some(format!(
+                "{} pairs of neighbours still match; a palette with more colors would fix that",
+                clashes
+            ));
+        }
+    }
+
+    fn document_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Document");
+
+        let mut size = self.document_size;
+        let dragging = ui.horizontal(|ui| {
+            let selected = DOCUMENT_PRESETS
+                .iter()
+                .find(|&&(_, preset)| preset == size)
+                .map_or("Custom", |&(name, _)| name);
+            egui::ComboBox::from_id_source("document size")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for &(name, preset) in DOCUMENT_PRESETS {
+                        let text = format!("{} ({}x{})", name, preset[0], preset[1]);
+                        ui.selectable_value(&mut size, preset, text);
+                    }
+                });
+
+            let max = project::MAX_DOCUMENT_SIZE;
+            let width = ui.add(egui::DragValue::new(&mut size[0]).clamp_range(1..=max));
+            ui.label("x");
+            let height = ui.add(egui::DragValue::new(&mut size[1]).clamp_range(1..=max));
+            width.dragged() || height.dragged()
+        });
+
+        if size != self.document_size {
+            // it clips the picture differently, so it's an edit like any other
+            if !self.resizing {
+                self.record();
+            }
+            self.set_document_size(size);
+        }
+        self.resizing = dragging.inner;
+    }
+
+    fn project_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Project");
+
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.project_path);
+
+            let path = std::path::Path::new(&self.project_path);
+            if ui.button("Save").clicked() {
+                self.status = Some(match self.to_project().save(path) {
+                    Ok(()) => "Project saved.".to_owned(),
+                    Err(e) => e.to_string(),
+                });
+            }
+            if ui.button("Load").clicked() {
+                match project::Project::load(path) {
+                    Ok(project) => {
+                        self.apply_project(project);
+                        self.status = Some("Project loaded.".to_owned());
+                    }
+                    Err(e) => self.status = Some(e.to_string()),
+                }
+            }
+        });
+
+        #[cfg(target_arch = "wasm32")]
+        if ui.button("Save project").clicked() {
+            let url = to_data_url(self.to_project().to_bytes(), "application/json");
+            wasm::save_data_url(url, format!("untitled.{}", project::EXTENSION));
+        }
+
+        ui.label("...or drop a project file onto the window to open it.");
+
+        if let Some(status) = &self.status {
+            ui.monospace(status);
         }
     }
 }
 fn vec_to_color(v: Oklab) -> egui::Color32 {
-    let v: Srgb = v.into_color();
-
-    egui::Color32::from_rgb(
-        (v.red * 256.0) as u8,
-        (v.green * 256.0) as u8,
-        (v.blue * 256.0) as u8,
-    )
+    let [r, g, b] = wrong_track::to_srgb8(wrong_track::to_srgb(v));
+    egui::Color32::from_rgb(r, g, b)
 }
 
 impl MyEguiApp {
-    fn random_point_in_disk(&self, radius: f32) -> glam::Vec2 {
-        let mut rng = thread_rng();
-        let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
-        let distance = rng.gen_range(0.0..radius);
-        let (x, y) = angle.sin_cos();
-        glam::vec2(x, y) * distance
-    }
-
-    fn random_color(&self, point: glam::Vec2) -> Oklab {
-        let mut rng = thread_rng();
-        if self.override_color_enabled {
-            let oc = self.override_color.to_srgb().map(|x| x as f32 / 256.0);
-            Srgb::from_components((oc[0], oc[1], oc[2])).into_color()
-        } else {
-            let random_color = Srgb::from_components((
-                rng.gen_range(0.0f32..1.0),
-                rng.gen_range(0.0f32..1.0),
-                rng.gen_range(0.0f32..1.0),
-            ))
-            .into_color();
-            let mut sampled_color = Oklab::default();
-            for _ in 0..self.num_color_samples {
-                let perturb = self.random_point_in_disk(0.05);
-                let sample_point = (point + perturb).clamp(glam::Vec2::ZERO, glam::Vec2::ONE);
-                let sample = self.bsp.get_at_point(sample_point);
-                sampled_color += *sample;
-            }
-            sampled_color *= 1.0 / (self.num_color_samples as f32);
-
-            sampled_color.mix(&random_color, self.color_randomness)
+    fn split_params(&self) -> generate::SplitParams {
+        generate::SplitParams {
+            normal_randomness: self.normal_randomness,
+            color_randomness: self.color_randomness,
+            num_color_samples: self.num_color_samples,
+            color_source: self.color_source,
+            override_color: self
+                .override_color_enabled
+                .then(|| generate::color_from_srgb8(self.override_color.to_srgb())),
+            canvas: self.canvas(),
+            constraints: self.split_constraints,
+            prune: self.auto_prune.then_some(self.prune_min_area),
+            palette_mode: self.palette_mode,
+            palette: self.palettes[self.palette].weighted(),
         }
     }
-    fn random_normal(&self, point: glam::Vec2) -> glam::Vec2 {
-        let mut rng = thread_rng();
-        let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
-        let (x, y) = angle.sin_cos();
-        let rand_normal = glam::vec2(x, y);
 
-        let rand_normal =
-            (point - glam::Vec2::new(0.5, 0.5)).lerp(rand_normal, self.normal_randomness);
-        rand_normal.normalize()
+    /// Wheel (or pinch) zooms around the cursor, middle-drag pans.
+    fn view_input(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        let rect = response.rect;
+        if response.dragged_by(egui::PointerButton::Middle) {
+            self.view.pan(response.drag_delta(), rect);
+        }
+        if let Some(hover_pos) = response.hover_pos() {
+            let input = ui.input();
+            let factor = input.zoom_delta() * (input.scroll_delta.y / 200.0).exp();
+            if factor != 1.0 {
+                self.view.zoom_around(hover_pos, factor, rect);
+            }
+        }
+    }
+
+    fn outline(&self, region: &Polygon, rect: egui::Rect, stroke: egui::Stroke) -> egui::Shape {
+        let points = region
+            .vertices
+            .iter()
+            .map(|&v| self.view.canvas_to_screen(v, rect))
+            .collect();
+        egui::Shape::closed_line(points, stroke)
+    }
+
+    /// The canvas point under screen position `pos`, if it's on the canvas.
+    fn canvas_pos(&self, pos: egui::Pos2, rect: egui::Rect) -> Option<glam::Vec2> {
+        in_canvas(self.view.screen_to_canvas(pos, rect), self.canvas())
+    }
+
+    fn random_color(&mut self, point: glam::Vec2) -> Oklab {
+        let params = self.split_params();
+        generate::random_color(&self.bsp, &self.regions, &mut self.rng, &params, point)
+    }
+    fn random_normal(&mut self, point: glam::Vec2) -> glam::Vec2 {
+        let params = self.split_params();
+        generate::random_normal(&mut self.rng, &params, point)
     }
 }
 impl eframe::App for MyEguiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let mut rng = thread_rng();
+        self.handle_dropped_files(ctx);
+        self.handle_undo_shortcuts(ctx);
 
         egui::SidePanel::right("right")
             .max_width(512.0)
             .show(ctx, |ui| {
-                ui.heading("wrong!track!");
-                ui.hyperlink_to(
-                    "source on GitHub",
-                    "https://github.com/XMPPwocky/wrongtrack",
-                );
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.heading("wrong!track!");
+                    ui.hyperlink_to(
+                        "source on GitHub",
+                        "https://github.com/XMPPwocky/wrongtrack",
+                    );
+
+                    ui.monospace(format!("BSP nodes: {}", self.bsp.len()));
+
+                    self.history_ui(ui);
+
+                    if ui.button("CLEAR ALL").clicked() {
+                        self.record();
+                        self.bsp = bsp::Bsp::new(Oklab::new(1.0, 0.0, 0.0));
+                        self.tree_changed(Changed::Everything);
+                        self.reseed(self.seed);
+                    }
 
-                ui.monospace(format!("BSP nodes: {}", self.bsp.len()));
+                    self.seed_ui(ui);
 
-                if ui.button("CLEAR ALL").clicked() {
-                    self.bsp = bsp::Bsp::new(Oklab::new(1.0, 0.0, 0.0));
-                }
+                    self.export_ui(ui);
 
-                if ui.button("Export SVG").clicked() {
-                    let url = to_data_url(save_svg(&self.bsp));
-                    open_url_new_tab(ui.ctx(), &url);
-                }
+                    self.project_ui(ui);
 
-                let label = ui.label("Normal randomness").id;
-                ui.add(egui::widgets::Slider::new(
-                    &mut self.normal_randomness,
-                    0.0..=1.0,
-                ))
-                .labelled_by(label);
+                    self.document_ui(ui);
 
-                ui.heading("Color");
+                    self.style_ui(ui);
 
-                let label = ui.label("Color randomness").id;
-                ui.add(egui::widgets::Slider::new(
-                    &mut self.color_randomness,
-                    0.0..=1.0,
-                ))
-                .labelled_by(label);
+                    self.constraints_ui(ui);
 
-                let label = ui.label("Num. color samples").id;
-                ui.add(
-                    egui::widgets::DragValue::new(&mut self.num_color_samples).clamp_range(1..=64),
-                )
-                .labelled_by(label);
+                    self.prune_ui(ui);
+
+                    let label = ui.label("Normal randomness").id;
+                    ui.add(egui::widgets::Slider::new(
+                        &mut self.normal_randomness,
+                        0.0..=1.0,
+                    ))
+                    .labelled_by(label);
+
+                    ui.heading("Color");
+
+                    let label = ui.label("Color randomness").id;
+                    ui.add(egui::widgets::Slider::new(
+                        &mut self.color_randomness,
+                        0.0..=1.0,
+                    ))
+                    .labelled_by(label);
+
+                    ui.horizontal(|ui| {
+                        ui.label("New colors from");
+                        ui.radio_value(&mut self.color_source, generate::ColorSource::Samples, "Samples")
+                            .on_hover_text("Points picked at random around the split");
+                        ui.radio_value(&mut self.color_source, generate::ColorSource::Neighbours, "Neighbours")
+                            .on_hover_text("The regions bordering the split one, weighted by shared edge");
+                    });
 
-                ui.group(|ui| {
-                    ui.checkbox(&mut self.override_color_enabled, "Override color?");
-                    ui.add_enabled_ui(self.override_color_enabled, |ui| {
-                        egui::widgets::color_picker::color_picker_hsva_2d(
-                            ui,
-                            &mut self.override_color,
-                            egui::widgets::color_picker::Alpha::Opaque,
+                    ui.add_enabled_ui(self.color_source == generate::ColorSource::Samples, |ui| {
+                        let label = ui.label("Num. color samples").id;
+                        ui.add(
+                            egui::widgets::DragValue::new(&mut self.num_color_samples).clamp_range(1..=64),
                         )
+                        .labelled_by(label);
                     });
+
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("SMOOTH")
+                            .on_hover_text("Relax every color towards its neighbours")
+                            .clicked()
+                        {
+                            self.record();
+                            generate::smooth(
+                                &mut self.bsp,
+                                &self.regions,
+                                self.smooth_strength,
+                                self.smooth_iterations,
+                            );
+                            self.tree_changed(Changed::Colors);
+                        }
+                        ui.add(
+                            egui::DragValue::new(&mut self.smooth_iterations)
+                                .clamp_range(1..=100)
+                                .suffix("x"),
+                        )
+                        .on_hover_text("Passes");
+                        ui.add(egui::Slider::new(&mut self.smooth_strength, 0.0..=1.0))
+                            .on_hover_text("How far each pass moves colors");
+                    });
+
+                    self.palette_ui(ui);
+
+                    self.coloring_ui(ui);
+
+                    ui.group(|ui| {
+                        ui.checkbox(&mut self.override_color_enabled, "Override color?");
+                        ui.add_enabled_ui(self.override_color_enabled, |ui| {
+                            egui::widgets::color_picker::color_picker_hsva_2d(
+                                ui,
+                                &mut self.override_color,
+                                egui::widgets::color_picker::Alpha::Opaque,
+                            )
+                        });
+                    });
+
+                    self.inspector.ui(
+                        ui,
+                        &self.bsp,
+                        &self.regions,
+                        self.revision,
+                        self.document_size,
+                        &mut self.selected,
+                    );
                 });
             });
+        if self.show_tree {
+            egui::SidePanel::left("tree")
+                .default_width(280.0)
+                .show(ctx, |ui| {
+                    ui.heading("Tree");
+                    ui.label("Click a row to select it, right-click to edit.");
+                    let action = egui::ScrollArea::both()
+                        .show(ui, |ui| self.tree_view.ui(ui, &self.bsp, &mut self.selected))
+                        .inner;
+                    if let Some(action) = action {
+                        self.apply_tree_action(action);
+                    }
+                });
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.separator();
 
             ui.horizontal(|ui| {
-                if ui.button("RND SPLIT [R]").clicked() || ui.input().key_pressed(egui::Key::R) {
-                    let rand_point =
-                        glam::Vec2::new(rng.gen_range(0.0f32..1.0), rng.gen_range(0.0f32..1.0));
-
-                    let rand_normal = self.random_normal(rand_point);
-
-                    let rand_color = self.random_color(rand_point);
-
-                    self.bsp.split_at_point(rand_point, rand_normal, rand_color);
+                // not while typing an R into a text field
+                let shortcut =
+                    !ui.ctx().wants_keyboard_input() && ui.input().key_pressed(egui::Key::R);
+                if ui.button("RND SPLIT [R]").clicked() || shortcut {
+                    let before = self.snapshot();
+                    let params = self.split_params();
+                    match generate::random_split(&mut self.bsp, &self.regions, &mut self.rng, &params) {
+                        Some(key) => {
+                            self.history.record(before);
+                            self.split_done(key);
+                        }
+                        None => self.status = Some(NO_ROOM.to_owned()),
+                    }
                 }
                 if ui.button("SPLIT X100").clicked() {
+                    // one undo step for all of them, if any worked out
+                    let mut before = Some(self.snapshot());
+                    let params = self.split_params();
+                    let mut missed = 0;
                     for _ in 0..100 {
-                        let rand_point =
-                            glam::Vec2::new(rng.gen_range(0.0f32..1.0), rng.gen_range(0.0f32..1.0));
-
-                        let rand_normal = self.random_normal(rand_point);
-
-                        let rand_color = self.random_color(rand_point);
-
-                        self.bsp.split_at_point(rand_point, rand_normal, rand_color);
+                        match generate::random_split(&mut self.bsp, &self.regions, &mut self.rng, &params) {
+                            Some(key) => {
+                                if let Some(before) = before.take() {
+                                    self.history.record(before);
+                                }
+                                self.split_done(key);
+                            }
+                            None => missed += 1,
+                        }
+                    }
+                    if missed > 0 {
+                        self.status = Some(format!("{} of 100: {}", missed, NO_ROOM));
                     }
                 }
                 ui.radio_value(&mut self.tool, Tool::Split, "Split");
                 ui.radio_value(&mut self.tool, Tool::Unsplit, "Unsplit");
                 ui.radio_value(&mut self.tool, Tool::Paint, "Paint");
+                ui.radio_value(&mut self.tool, Tool::Select, "Select");
+                ui.separator();
+                if ui
+                    .add_enabled(
+                        self.view != view::View::fit(self.canvas()),
+                        egui::Button::new("Reset view"),
+                    )
+                    .clicked()
+                {
+                    self.view = view::View::fit(self.canvas());
+                }
+                ui.checkbox(&mut self.show_splits, "Show splits")
+                    .on_hover_text("Draw every cut, colored by depth");
+                ui.checkbox(&mut self.show_gamut, "Show out of gamut")
+                    .on_hover_text("Outline regions whose colors sRGB can't show, which come out with less chroma");
+                ui.checkbox(&mut self.show_tree, "Tree");
             });
 
             ui.separator();
             let sense = egui::Sense::click_and_drag();
-            let (response, painter) = ui.allocate_painter(egui::Vec2::new(512.0, 512.0), sense);
+            let (response, painter) = ui.allocate_painter(ui.available_size(), sense);
+            let rect = response.rect;
+            self.view_input(ui, &response);
+
             if self.tool == Tool::Split {
                 if response.hovered() {
                     ui.ctx().output().cursor_icon = egui::CursorIcon::Crosshair;
                 }
                 if response.clicked() && !response.drag_released() {
-                    if let Some(pos) = response.interact_pointer_pos() {
-                        let response_size = response.rect.size();
-
-                        let rel_pos = (pos - response.rect.min) / response_size;
-
+                    if let Some(rel_pos) = response
+                        .interact_pointer_pos()
+                        .and_then(|pos| self.canvas_pos(pos, rect))
+                    {
                         let rand_normal =
-                            glam::Vec2::new(rng.gen_range(0.0f32..1.0), rng.gen_range(0.0f32..1.0))
+                            glam::Vec2::new(self.rng.gen_range(0.0f32..1.0), self.rng.gen_range(0.0f32..1.0))
                                 .normalize();
-                        let rand_color =
-                            self.random_color(<[f32; 2] as From<_>>::from(rel_pos).into());
+                        let rand_color = self.random_color(rel_pos);
 
-                        self.bsp.split_at_point(
-                            <[f32; 2] as From<_>>::from(rel_pos).into(),
-                            rand_normal,
-                            rand_color,
-                        );
+                        self.split(rel_pos, rand_normal, rand_color);
                     }
                 }
                 if response.drag_started() {
-                    if let Some(pos) = response.interact_pointer_pos() {
-                        self.drag_start_pos = pos;
-                    }
+                    // middle drags pan instead
+                    self.drag_start_pos = response
+                        .interact_pointer_pos()
+                        .filter(|_| ui.input().pointer.primary_down());
                 }
                 if response.drag_released() {
-                    if let Some(pos) = response.interact_pointer_pos() {
-                        let response_size = response.rect.size();
-
-                        let middle_pos = (pos + self.drag_start_pos.to_vec2()).to_vec2() * 0.5;
-                        let rel_pos = (middle_pos - response.rect.min.to_vec2()) / response_size;
-                        let rel_pos = <[f32; 2] as From<_>>::from(rel_pos).into();
-
-                        let rand_color = self.random_color(rel_pos);
-
-                        let drag_normal = (pos - self.drag_start_pos).normalized();
-                        let drag_normal = if drag_normal.length_sq() < 1e-6 {
-                            self.random_normal(rel_pos)
-                        } else {
-                            <[f32; 2] as From<_>>::from(drag_normal).into()
-                        };
-                        self.bsp.split_at_point(rel_pos, drag_normal, rand_color);
+                    if let (Some(start), Some(pos)) =
+                        (self.drag_start_pos.take(), response.interact_pointer_pos())
+                    {
+                        let start = self.view.screen_to_canvas(start, rect);
+                        let end = self.view.screen_to_canvas(pos, rect);
+                        let middle_pos = (start + end) * 0.5;
+
+                        if let Some(rel_pos) = in_canvas(middle_pos, self.canvas()) {
+                            let rand_color = self.random_color(rel_pos);
+
+                            let drag_normal = (end - start).normalize_or_zero();
+                            let drag_normal = if drag_normal == glam::Vec2::ZERO {
+                                self.random_normal(rel_pos)
+                            } else {
+                                drag_normal
+                            };
+                            self.split(rel_pos, drag_normal, rand_color);
+                        }
                     }
                 }
             } else if self.tool == Tool::Paint {
                 if response.hovered() {
                     ui.ctx().output().cursor_icon = egui::CursorIcon::Crosshair;
                 }
-                if response.is_pointer_button_down_on() {
-                    if let Some(pos) = response.interact_pointer_pos() {
-                        let response_size = response.rect.size();
-
-                        let rel_pos = (pos - response.rect.min) / response_size;
-                        let rel_pos = <egui::Vec2 as Into<[f32; 2]>>::into(rel_pos).into();
-
+                if response.is_pointer_button_down_on() && ui.input().pointer.primary_down() {
+                    if let Some(rel_pos) = response
+                        .interact_pointer_pos()
+                        .and_then(|pos| self.canvas_pos(pos, rect))
+                    {
+                        if !self.painting {
+                            self.record();
+                            self.painting = true;
+                        }
                         *self.bsp.get_at_point_mut(rel_pos) = self.random_color(rel_pos);
+                        self.tree_changed(Changed::Colors);
+                    }
+                } else {
+                    self.painting = false;
+                }
+            } else if self.tool == Tool::Select {
+                if let Some(key) = self.selected {
+                    let (history, rng, document_size) =
+                        (&mut self.history, &self.rng, self.document_size);
+                    let moved = self.split_editor.interact(
+                        ui,
+                        &response,
+                        self.view,
+                        &mut self.bsp,
+                        &self.regions,
+                        |bsp| {
+                            history.record(history::Snapshot {
+                                bsp: bsp.clone(),
+                                rng: rng.clone(),
+                                document_size,
+                            })
+                        },
+                        key,
+                    );
+                    if moved {
+                        self.tree_changed(Changed::Subtree(key));
                     }
                 }
+                if response.clicked() {
+                    self.selected = response
+                        .interact_pointer_pos()
+                        .and_then(|pos| self.canvas_pos(pos, rect))
+                        .map(|rel_pos| self.bsp.leaf_index_for_point(rel_pos));
+                }
             } else if self.tool == Tool::Unsplit {
                 if response.hovered() {
                     ui.ctx().output().cursor_icon = egui::CursorIcon::Crosshair;
                 }
                 if response.clicked() {
-                    if let Some(pos) = response.interact_pointer_pos() {
-                        let response_size = response.rect.size();
-
-                        let rel_pos = (pos - response.rect.min) / response_size;
-                        let rel_pos = <egui::Vec2 as Into<[f32; 2]>>::into(rel_pos).into();
-
-                        self.bsp.unsplit_at_point(rel_pos);
+                    if let Some(rel_pos) = response
+                        .interact_pointer_pos()
+                        .and_then(|pos| self.canvas_pos(pos, rect))
+                    {
+                        // a lone root leaf has nothing to unsplit
+                        if self.bsp.len() > 1 {
+                            self.record();
+                            if let Some(parent) = self.bsp.unsplit_at_point(rel_pos) {
+                                self.tree_changed(Changed::Subtree(parent));
+                            }
+                        }
                     }
-                }               
+                }
             }
 
-            let outer_poly =
-                bsp::Polygon::new_rect(glam::Vec2::new(0.0, 0.0), glam::Vec2::new(1.0, 1.0));
+            painter.add(self.leaf_mesh.shape(
+                self.view,
+                rect,
+                &self.regions,
+                &self.bsp,
+                &self.style,
+            ));
+
+            let hovered = response
+                .hover_pos()
+                .and_then(|pos| self.canvas_pos(pos, rect))
+                .map(|rel_pos| self.bsp.leaf_index_for_point(rel_pos));
+            if self.show_splits {
+                self.split_overlay.paint(
+                    &painter,
+                    self.view,
+                    rect,
+                    self.revision,
+                    &self.bsp,
+                    &self.regions,
+                    hovered,
+                );
+            }
 
-            self.bsp
-                .visit_leaf_polygons(self.bsp.root_key(), outer_poly, &mut |leaf, poly| {
-                    let fill_color = leaf;
+            if self.show_gamut {
+                self.gamut_overlay
+                    .paint(&painter, self.view, rect, self.revision, &self.bsp, &self.regions);
+            }
 
-                    let shapy = egui::Shape::Path(egui::epaint::PathShape {
-                        closed: true,
-                        fill: vec_to_color(fill_color.0),
-                        stroke: egui::Stroke::new(0.0, egui::Color32::TRANSPARENT),
-                        points: poly_to_egui_points(&poly, response.rect),
-                    });
-                    painter.add(shapy);
-                });
+            if self.tool == Tool::Select && !self.split_editor.is_dragging() {
+                if let Some(region) = hovered.and_then(|key| self.regions.region(key)) {
+                    let stroke = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(160));
+                    painter.add(self.outline(region, rect, stroke));
+                }
+            }
+            if let Some(region) = self.selected.and_then(|key| self.regions.region(key)) {
+                // dark under light, so it shows up on any color
+                painter.add(self.outline(region, rect, egui::Stroke::new(4.0, egui::Color32::BLACK)));
+                painter.add(self.outline(region, rect, egui::Stroke::new(2.0, egui::Color32::WHITE)));
+            }
+            if let (Tool::Select, Some(key)) = (&self.tool, self.selected) {
+                self.split_editor
+                    .paint(&painter, self.view, rect, &self.bsp, &self.regions, key);
+            }
         });
     }
-}
 
-fn poly_to_egui_points(poly: &Polygon, out_rect: egui::Rect) -> Vec<egui::Pos2> {
-    let x_range = out_rect.x_range();
-    let x_dist = x_range.end() - x_range.start();
-    let y_range = out_rect.y_range();
-    let y_dist = y_range.end() - y_range.start();
-
-    poly.vertices
-        .iter()
-        .map(|&vert| {
-            let x = x_range.start() + (vert.x * x_dist);
-            let y = y_range.start() + (vert.y * y_dist);
-            egui::Pos2::new(x, y)
-        })
-        .collect()
+    fn on_exit(&mut self, gl: Option<&eframe::glow::Context>) {
+        if let Some(gl) = gl {
+            self.leaf_mesh.destroy(gl);
+        }
+    }
 }
 
-fn save_svg(bsp: &bsp::Bsp<Oklab>) -> Vec<u8> {
-    use svg::node::element::path::Data;
-    use svg::node::element::Path;
-    use svg::Document;
-
-    let mut document = Some(Document::new().set("viewBox", (0.0, 0.0, 1.0, 1.0)));
-
-    bsp.visit_leaf_polygons(
-        bsp.root_key(),
-        bsp::Polygon::new_rect(glam::Vec2::ZERO, glam::Vec2::ONE),
-        &mut |leaf, poly| {
-            let color: Srgb = leaf.0.into_color();
-            let color = format!(
-                "rgb({}, {}, {})",
-                (color.red * 256.0) as u8,
-                (color.green * 256.0) as u8,
-                (color.blue * 256.0) as u8
-            );
-
-            let mut data = Data::new();
-            data = data.move_to((poly.vertices[0].x, poly.vertices[0].y));
-            for vert in &poly.vertices[1..] {
-                data = data.line_to((vert.x, vert.y));
-            }
-            data = data.close();
-
-            let path = Path::new()
-                .set("fill", color)
-                .set("stroke", "none")
-                .set("stroke-width", 0)
-                .set("d", data);
-
-            document = Some(document.take().unwrap().add(path));
-        },
-    );
+/// What the status line says when random splits give up.
+const NO_ROOM: &str = "no random split fit within the split limits";
+
+/// Sizes people keep asking for, in pixels.
+const DOCUMENT_PRESETS: &[(&str, [u32; 2])] = &[
+    ("Square", [2048, 2048]),
+    ("1080p", [1920, 1080]),
+    ("4K", [3840, 2160]),
+    ("Phone", [1080, 1920]),
+    ("Banner", [1500, 500]),
+    ("A4 at 300 dpi", [2480, 3508]),
+    ("A3 at 300 dpi", [3508, 4961]),
+    ("A2 at 300 dpi", [4961, 7016]),
+];
+
+fn canvas_size([width, height]: [u32; 2]) -> glam::Vec2 {
+    wrong_track::canvas_size(width, height)
+}
 
-    let mut w = Vec::new();
-    svg::write(&mut w, &document.unwrap()).unwrap();
-    w
+/// `v`, if it's inside the region the root covers. Zoomed out, there's
+/// empty space around the canvas that edits shouldn't reach into.
+fn in_canvas(v: glam::Vec2, canvas: glam::Vec2) -> Option<glam::Vec2> {
+    let inside = (0.0..=canvas.x).contains(&v.x) && (0.0..=canvas.y).contains(&v.y);
+    inside.then_some(v)
 }
 
-fn to_data_url(data: Vec<u8>) -> String {
+fn to_data_url(data: Vec<u8>, mime: &str) -> String {
     let data = base64::engine::general_purpose::STANDARD_NO_PAD.encode(data);
-    let url = format!("data:image/svg+xml;base64,{}", data);
+    let url = format!("data:{};base64,{}", mime, data);
     url
 }
 
@@ -399,7 +1426,7 @@ fn open_url_new_tab(ctx: &egui::Context, url: &str) {
 
 #[cfg(target_arch = "wasm32")]
 fn open_url_new_tab(_ctx: &egui::Context, url: &str) {
-    wasm::save_data_url(url.to_owned());
+    wasm::save_data_url(url.to_owned(), "download.svg".to_owned());
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -408,6 +1435,6 @@ mod wasm {
 
     #[wasm_bindgen(module = "/save.js")]
     extern "C" {
-        pub fn save_data_url(x: String);
+        pub fn save_data_url(x: String, filename: String);
     }
 }
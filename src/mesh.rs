@@ -0,0 +1,785 @@
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use eframe::egui;
+use eframe::egui_glow;
+use eframe::glow::{self, HasContext as _};
+use egui::epaint::{Color32, Mesh, WHITE_UV};
+use glam::Vec2;
+use palette::{Oklab, Srgb};
+use slotmap::SecondaryMap;
+use wrong_track::{bsp, BspNode, Polygon, RegionCache, Style};
+
+use crate::view::View;
+use crate::Changed;
+
+/// How far, in screen pixels, each leaf is grown past its true outline.
+/// Neighbours overlap by this much, which covers the cracks the GPU leaves at
+/// T-junctions (a vertex of one leaf lying on an edge of another).
+const OVERLAP: f32 = 0.5;
+/// Width of the antialiasing fringe around each leaf, in screen pixels.
+const FEATHER: f32 = 1.0;
+/// How far leaves are grown with a gutter between them. There are no cracks
+/// to cover then, and growing would only narrow the gutter, so the fringe is
+/// centered on the true outline instead, the way the exporters draw it.
+const GUTTER_OVERLAP: f32 = -0.5 * FEATHER;
+/// Sharp corners get grown along a miter; this keeps thin slivers from
+/// sprouting long spikes.
+const MITER_LIMIT: f32 = 4.0;
+/// Squared edge lengths and twice the areas below this, in canvas units,
+/// count as nothing. On a thousand pixel wide view that's a millionth of a
+/// pixel.
+const EPSILON: f32 = 1e-12;
+/// More separate changes than this since the last upload, and it's quicker
+/// to upload everything.
+const MAX_DIRTY_RANGES: usize = 64;
+
+/// All the leaves, as one mesh.
+///
+/// Each convex leaf is fanned into triangles, grown a little so neighbours
+/// overlap instead of leaving hairline gaps, and given a thin fringe that
+/// fades out for antialiasing. Since neighbours overlap underneath, the
+/// fringe fades into the next leaf's color rather than into the background.
+///
+/// Outlines and the gutter color go underneath, laid out by the same
+/// [`Style`] the exporters use.
+///
+/// The mesh is kept in canvas coordinates, with the growing and the fringe
+/// as offsets in screen pixels, so panning and zooming only change how it's
+/// drawn. Edits patch the leaves they touched (see [`LeafMesh::changed`]),
+/// and only a new style or a new tree builds it all again. With OpenGL it's
+/// drawn by a paint callback from buffers that only get the patched parts
+/// uploaded; otherwise it's handed to egui as a shape every frame.
+pub struct LeafMesh {
+    layers: Arc<Mutex<Layers>>,
+    // where each leaf's triangles are
+    slots: SecondaryMap<bsp::BspKey, Slot>,
+    // the style it was built with, or None if it has to be built again
+    style: Option<Style>,
+    // what changed since it was last brought up to date
+    subtrees: Vec<bsp::BspKey>,
+    colors: bool,
+    gpu: Option<Arc<Mutex<GpuMesh>>>,
+}
+
+impl LeafMesh {
+    /// Draws through `gl` if there is one and it has what it takes, and
+    /// through egui shapes otherwise.
+    pub fn new(gl: Option<&glow::Context>) -> LeafMesh {
+        LeafMesh {
+            layers: Arc::default(),
+            slots: SecondaryMap::new(),
+            style: None,
+            subtrees: Vec::new(),
+            colors: false,
+            gpu: gl
+                .and_then(GpuMesh::new)
+                .map(|gpu| Arc::new(Mutex::new(gpu))),
+        }
+    }
+
+    /// Notes an edit to the tree, to be caught up with when it's next drawn.
+    /// Everything that changes the tree or the region cache's bounds has to
+    /// come through here.
+    pub fn changed(&mut self, changed: &Changed) {
+        match *changed {
+            Changed::Colors => self.colors = true,
+            Changed::Subtree(key) => self.subtrees.push(key),
+            Changed::Everything => self.style = None,
+        }
+    }
+
+    /// A shape drawing the tree, seen through `view` in `rect`.
+    pub fn shape(
+        &mut self,
+        view: View,
+        rect: egui::Rect,
+        regions: &RegionCache,
+        bsp: &bsp::Bsp<Oklab>,
+        style: &Style,
+    ) -> egui::Shape {
+        self.update(regions, bsp, style);
+
+        let gpu = match &self.gpu {
+            Some(gpu) => gpu.clone(),
+            None => return egui::Shape::mesh(self.screen_mesh(view, rect)),
+        };
+        let layers = self.layers.clone();
+        let (center, scale) = view.center_and_scale(rect);
+        let transform = [center.x, center.y, scale];
+        let rect_center = rect.center();
+        let callback = egui_glow::CallbackFn::new(move |info, painter| {
+            if let (Ok(mut gpu), Ok(mut layers)) = (gpu.lock(), layers.lock()) {
+                gpu.paint(painter.gl(), &mut layers, transform, rect_center, &info);
+            }
+        });
+        egui::Shape::Callback(egui::PaintCallback {
+            rect,
+            callback: Arc::new(callback),
+        })
+    }
+
+    /// Frees what's on the GPU. Call with the same context as [`LeafMesh::new`].
+    pub fn destroy(&self, gl: &glow::Context) {
+        if let Some(Ok(gpu)) = self.gpu.as_ref().map(|gpu| gpu.lock()) {
+            gpu.destroy(gl);
+        }
+    }
+
+    // brings the mesh up to date with the tree, patching what it can
+    fn update(&mut self, regions: &RegionCache, bsp: &bsp::Bsp<Oklab>, style: &Style) {
+        let shared = self.layers.clone();
+        let mut layers = match shared.lock() {
+            Ok(layers) => layers,
+            Err(_) => return,
+        };
+        if self.style.as_ref() != Some(style) {
+            self.rebuild(&mut layers, regions, bsp, style);
+            return;
+        }
+
+        if !self.subtrees.is_empty() {
+            // every leaf there is now under the edited nodes; the leaves
+            // that were there before are either among them or gone
+            let mut fresh = SecondaryMap::new();
+            for start in self.subtrees.drain(..) {
+                let mut stack = vec![start];
+                while let Some(key) = stack.pop() {
+                    match bsp.get(key) {
+                        Some(BspNode::Inode(inode)) => stack.extend([inode.le, inode.gt]),
+                        Some(BspNode::Leaf(_)) => {
+                            fresh.insert(key, ());
+                        }
+                        None => {}
+                    }
+                }
+            }
+
+            let stale: Vec<_> = self
+                .slots
+                .keys()
+                .filter(|&key| fresh.contains_key(key) || bsp.leaf_value(key).is_none())
+                .collect();
+            for key in stale {
+                if let Some(slot) = self.slots.remove(key) {
+                    layers.remove(&slot);
+                }
+            }
+            let overlap = overlap_for(style);
+            for key in fresh.keys() {
+                if let (Some(poly), Some(&color)) = (regions.region(key), bsp.leaf_value(key)) {
+                    let slot = layers.add(poly, crate::vec_to_color(color), style, overlap);
+                    self.slots.insert(key, slot);
+                }
+            }
+
+            // once most of it is blanked out, it's worth starting over
+            if layers.garbage() > layers.live() {
+                self.rebuild(&mut layers, regions, bsp, style);
+                return;
+            }
+        }
+
+        if self.colors {
+            self.colors = false;
+            for (key, slot) in &mut self.slots {
+                let color = match bsp.leaf_value(key) {
+                    Some(&color) => crate::vec_to_color(color),
+                    None => continue,
+                };
+                if color != slot.color {
+                    slot.color = color;
+                    if let Some(fill) = &slot.fill {
+                        layers.fills.set_color(fill, color);
+                    }
+                }
+            }
+        }
+    }
+
+    fn rebuild(
+        &mut self,
+        layers: &mut Layers,
+        regions: &RegionCache,
+        bsp: &bsp::Bsp<Oklab>,
+        style: &Style,
+    ) {
+        *layers = Layers::default();
+        self.slots.clear();
+        self.subtrees.clear();
+        self.colors = false;
+
+        if let Some(color) = style.gutter_color {
+            layers
+                .outlines
+                .add(regions.bounds(), srgb_to_color(color), OVERLAP);
+        }
+        let overlap = overlap_for(style);
+        for (key, poly) in regions.leaves() {
+            if let Some(&color) = bsp.leaf_value(key) {
+                let slot = layers.add(poly, crate::vec_to_color(color), style, overlap);
+                self.slots.insert(key, slot);
+            }
+        }
+        self.style = Some(style.clone());
+    }
+
+    // the mesh where it is on screen, for egui to draw
+    fn screen_mesh(&self, view: View, rect: egui::Rect) -> Mesh {
+        let mut mesh = Mesh::default();
+        let layers = match self.layers.lock() {
+            Ok(layers) => layers,
+            Err(_) => return mesh,
+        };
+        for layer in [&layers.outlines, &layers.fills] {
+            let base = mesh.vertices.len() as u32;
+            mesh.vertices.extend(layer.vertices.iter().map(|v| {
+                let [r, g, b, a] = v.color;
+                egui::epaint::Vertex {
+                    pos: view.canvas_to_screen(Vec2::from(v.pos), rect)
+                        + egui::Vec2::from(v.offset),
+                    uv: WHITE_UV,
+                    color: Color32::from_rgba_premultiplied(r, g, b, a),
+                }
+            }));
+            mesh.indices.extend(layer.indices.iter().map(|i| base + i));
+        }
+        mesh
+    }
+}
+
+fn overlap_for(style: &Style) -> f32 {
+    if style.gutter > 0.0 {
+        GUTTER_OVERLAP
+    } else {
+        OVERLAP
+    }
+}
+
+fn srgb_to_color(color: Srgb) -> Color32 {
+    // rounded the same way as leaf colors, so an outline the same color as a
+    // leaf looks it
+    let [r, g, b] = wrong_track::to_srgb8(color);
+    Color32::from_rgb(r, g, b)
+}
+
+// a corner of a leaf: where it is on the canvas, and how far from there it's
+// pushed on screen, in points
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Vertex {
+    pos: [f32; 2],
+    offset: [f32; 2],
+    // premultiplied sRGB, like egui's
+    color: [u8; 4],
+}
+
+// SAFETY: plain numbers all the way down, with no padding between them
+unsafe impl bytemuck::Zeroable for Vertex {}
+unsafe impl bytemuck::Pod for Vertex {}
+
+// where in a layer one polygon's triangles are
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    vertices: Range<usize>,
+    indices: Range<usize>,
+}
+
+// one leaf's outline and fill, either of which may have been too small to
+// draw, and the color the fill has
+struct Slot {
+    outline: Option<Span>,
+    fill: Option<Span>,
+    color: Color32,
+}
+
+// the background and outlines, which go under all of the fills
+#[derive(Default)]
+struct Layers {
+    outlines: Layer,
+    fills: Layer,
+}
+
+impl Layers {
+    fn add(&mut self, poly: &Polygon, color: Color32, style: &Style, overlap: f32) -> Slot {
+        let outline_color = srgb_to_color(style.outline_color);
+        Slot {
+            outline: style
+                .outline_region(poly)
+                .and_then(|outline| self.outlines.add(&outline, outline_color, overlap)),
+            fill: self.fills.add(&style.fill_region(poly), color, overlap),
+            color,
+        }
+    }
+
+    fn remove(&mut self, slot: &Slot) {
+        if let Some(outline) = &slot.outline {
+            self.outlines.remove(outline);
+        }
+        if let Some(fill) = &slot.fill {
+            self.fills.remove(fill);
+        }
+    }
+
+    // how many indices are blanked out, and how many still draw something
+    fn garbage(&self) -> usize {
+        self.outlines.garbage + self.fills.garbage
+    }
+    fn live(&self) -> usize {
+        self.outlines.indices.len() + self.fills.indices.len() - self.garbage()
+    }
+}
+
+// triangles for one draw call. Polygons are added at the end and taken out by
+// blanking their triangles, and what changed is remembered until it's
+// uploaded.
+#[derive(Default)]
+struct Layer {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    garbage: usize,
+    dirty: Dirty,
+}
+
+impl Layer {
+    // `overlap` is how far the solid part is grown past `poly`, in screen
+    // pixels. None if `poly` is too small to draw.
+    fn add(&mut self, poly: &Polygon, color: Color32, overlap: f32) -> Option<Span> {
+        let mut points = poly.vertices.clone();
+        // zero-length edges have no direction to grow along
+        points.dedup_by(|a, b| a.distance_squared(*b) < EPSILON);
+        while points.len() > 1 && points[0].distance_squared(points[points.len() - 1]) < EPSILON {
+            points.pop();
+        }
+        if points.len() < 3 {
+            return None;
+        }
+
+        let twice_area: f32 = (0..points.len())
+            .map(|i| points[i].perp_dot(points[(i + 1) % points.len()]))
+            .sum();
+        if twice_area.abs() < EPSILON {
+            return None;
+        }
+        // which way is out depends on the winding. The view only scales and
+        // moves the canvas, so out is the same way on screen.
+        let outward = if twice_area > 0.0 { 1.0 } else { -1.0 };
+
+        let n = points.len() as u32;
+        let base = self.vertices.len() as u32;
+        let start = (self.vertices.len(), self.indices.len());
+        self.vertices.reserve(2 * n as usize);
+        self.indices.reserve(3 * 3 * n as usize);
+
+        let [r, g, b, a] = color.to_array();
+        for i in 0..points.len() {
+            let prev = points[(i + points.len() - 1) % points.len()];
+            let here = points[i];
+            let next = points[(i + 1) % points.len()];
+
+            let edge_normal = |a: Vec2, b: Vec2| (b - a).normalize().perp() * -outward;
+            let n_in = edge_normal(prev, here);
+            let n_out = edge_normal(here, next);
+
+            // offsetting both edges by 1 moves their corner this far
+            let miter = n_in + n_out;
+            let miter = if miter.length_squared() < 1e-6 {
+                n_in
+            } else {
+                let miter = miter.normalize();
+                miter / miter.dot(n_in).max(1.0 / MITER_LIMIT)
+            };
+
+            self.vertices.push(Vertex {
+                pos: here.into(),
+                offset: (miter * overlap).into(),
+                color: [r, g, b, a],
+            });
+            self.vertices.push(Vertex {
+                pos: here.into(),
+                offset: (miter * (overlap + FEATHER)).into(),
+                color: [0; 4],
+            });
+        }
+
+        // even vertices are the solid leaf, odd ones the faded edge of the fringe
+        for i in 1..n - 1 {
+            self.indices
+                .extend([base, base + 2 * i, base + 2 * (i + 1)]);
+        }
+        for i in 0..n {
+            let j = (i + 1) % n;
+            let (inner_i, outer_i) = (base + 2 * i, base + 2 * i + 1);
+            let (inner_j, outer_j) = (base + 2 * j, base + 2 * j + 1);
+            self.indices.extend([inner_i, outer_i, outer_j]);
+            self.indices.extend([inner_i, outer_j, inner_j]);
+        }
+
+        let span = Span {
+            vertices: start.0..self.vertices.len(),
+            indices: start.1..self.indices.len(),
+        };
+        self.dirty.mark(&span.vertices, &span.indices);
+        Some(span)
+    }
+
+    // blanks out the triangles, leaving the vertices unused
+    fn remove(&mut self, span: &Span) {
+        let first = span.vertices.start as u32;
+        self.indices[span.indices.clone()].fill(first);
+        self.garbage += span.indices.len();
+        self.dirty.mark(&(0..0), &span.indices);
+    }
+
+    fn set_color(&mut self, span: &Span, color: Color32) {
+        // the solid ones; the fringe fades to nothing whatever the color
+        for vertex in self.vertices[span.vertices.clone()].iter_mut().step_by(2) {
+            vertex.color = color.to_array();
+        }
+        self.dirty.mark(&span.vertices, &(0..0));
+    }
+}
+
+// the parts of a layer that changed since it was last uploaded
+#[derive(Default)]
+struct Dirty {
+    all: bool,
+    vertices: Vec<Range<usize>>,
+    indices: Vec<Range<usize>>,
+}
+
+impl Dirty {
+    fn mark(&mut self, vertices: &Range<usize>, indices: &Range<usize>) {
+        if self.all {
+            return;
+        }
+        for (ranges, range) in [(&mut self.vertices, vertices), (&mut self.indices, indices)] {
+            if range.is_empty() {
+                continue;
+            }
+            // most often right after the last one, when leaves get added
+            match ranges.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => ranges.push(range.clone()),
+            }
+        }
+        if self.vertices.len() + self.indices.len() > MAX_DIRTY_RANGES {
+            self.all = true;
+            self.vertices.clear();
+            self.indices.clear();
+        }
+    }
+}
+
+// the mesh in GPU buffers, and the shaders drawing it just like egui's would:
+// its colors are the same premultiplied sRGB, and egui leaves blending set up
+// for that when it calls back
+struct GpuMesh {
+    program: glow::Program,
+    // outlines, then fills
+    layers: [GpuLayer; 2],
+    u_viewport: glow::UniformLocation,
+    u_pixels_per_point: glow::UniformLocation,
+    u_transform: glow::UniformLocation,
+    u_rect_center: glow::UniformLocation,
+}
+
+struct GpuLayer {
+    vertex_array: glow::VertexArray,
+    vertices: glow::Buffer,
+    indices: glow::Buffer,
+    // how many of each the buffers have room for
+    vertex_capacity: usize,
+    index_capacity: usize,
+    index_count: i32,
+}
+
+const VERTEX_SHADER: &str = r#"
+uniform vec4 u_viewport; // min x, min y, width, height, in pixels
+uniform float u_pixels_per_point;
+uniform vec3 u_transform; // the canvas point at the rect's center, points per canvas unit
+uniform vec2 u_rect_center; // in points
+in vec2 a_pos; // in canvas units
+in vec2 a_offset; // in points
+in vec4 a_srgba;
+out vec4 v_rgba;
+
+void main() {
+    // View::canvas_to_screen, then out from there
+    vec2 pos = u_rect_center + (a_pos - u_transform.xy) * u_transform.z + a_offset;
+    vec2 p = (pos * u_pixels_per_point - u_viewport.xy) / u_viewport.zw;
+    gl_Position = vec4(2.0 * p.x - 1.0, 1.0 - 2.0 * p.y, 0.0, 1.0);
+    v_rgba = a_srgba / 255.0;
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#ifdef GL_ES
+    precision mediump float;
+#endif
+in vec4 v_rgba;
+out vec4 f_color;
+
+void main() {
+    f_color = v_rgba;
+}
+"#;
+
+impl GpuMesh {
+    // None on anything older than OpenGL 3.1 or WebGL 2, which might not
+    // have vertex arrays, or if the shaders don't build; the shapes do fine
+    // there
+    fn new(gl: &glow::Context) -> Option<GpuMesh> {
+        let version = egui_glow::ShaderVersion::get(gl);
+        if !version.is_new_shader_interface() {
+            return None;
+        }
+
+        unsafe {
+            let program = gl.create_program().ok()?;
+            let mut shaders = Vec::new();
+            for (kind, source) in [
+                (glow::VERTEX_SHADER, VERTEX_SHADER),
+                (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+            ] {
+                let shader = gl.create_shader(kind).ok()?;
+                gl.shader_source(
+                    shader,
+                    &format!("{}{}", version.version_declaration(), source),
+                );
+                gl.compile_shader(shader);
+                gl.attach_shader(program, shader);
+                shaders.push(shader);
+            }
+            gl.link_program(program);
+            for shader in shaders {
+                gl.detach_shader(program, shader);
+                gl.delete_shader(shader);
+            }
+            if !gl.get_program_link_status(program) {
+                gl.delete_program(program);
+                return None;
+            }
+
+            let u_viewport = gl.get_uniform_location(program, "u_viewport")?;
+            let u_pixels_per_point = gl.get_uniform_location(program, "u_pixels_per_point")?;
+            let u_transform = gl.get_uniform_location(program, "u_transform")?;
+            let u_rect_center = gl.get_uniform_location(program, "u_rect_center")?;
+            let attributes = [
+                gl.get_attrib_location(program, "a_pos")?,
+                gl.get_attrib_location(program, "a_offset")?,
+                gl.get_attrib_location(program, "a_srgba")?,
+            ];
+
+            Some(GpuMesh {
+                program,
+                layers: [
+                    GpuLayer::new(gl, attributes)?,
+                    GpuLayer::new(gl, attributes)?,
+                ],
+                u_viewport,
+                u_pixels_per_point,
+                u_transform,
+                u_rect_center,
+            })
+        }
+    }
+
+    // draws `layers`, uploading what changed in them first
+    fn paint(
+        &mut self,
+        gl: &glow::Context,
+        layers: &mut Layers,
+        transform: [f32; 3],
+        rect_center: egui::Pos2,
+        info: &egui::PaintCallbackInfo,
+    ) {
+        // where egui pointed the GL viewport, rounded to pixels the same way
+        let ppp = info.pixels_per_point;
+        let min = (info.viewport.min.to_vec2() * ppp).round();
+        let size = (info.viewport.max.to_vec2() * ppp).round() - min;
+
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.uniform_4_f32(Some(&self.u_viewport), min.x, min.y, size.x, size.y);
+            gl.uniform_1_f32(Some(&self.u_pixels_per_point), ppp);
+            let [x, y, scale] = transform;
+            gl.uniform_3_f32(Some(&self.u_transform), x, y, scale);
+            gl.uniform_2_f32(Some(&self.u_rect_center), rect_center.x, rect_center.y);
+
+            let [outlines, fills] = &mut self.layers;
+            for (gpu, layer) in [(outlines, &mut layers.outlines), (fills, &mut layers.fills)] {
+                gl.bind_vertex_array(Some(gpu.vertex_array));
+                gpu.upload(gl, layer);
+                gl.draw_elements(glow::TRIANGLES, gpu.index_count, glow::UNSIGNED_INT, 0);
+            }
+            gl.bind_vertex_array(None);
+        }
+    }
+
+    fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.program);
+            for layer in &self.layers {
+                gl.delete_vertex_array(layer.vertex_array);
+                gl.delete_buffer(layer.vertices);
+                gl.delete_buffer(layer.indices);
+            }
+        }
+    }
+}
+
+impl GpuLayer {
+    // `attributes` are where the shaders want the position, offset and color
+    unsafe fn new(gl: &glow::Context, [a_pos, a_offset, a_srgba]: [u32; 3]) -> Option<GpuLayer> {
+        let vertex_array = gl.create_vertex_array().ok()?;
+        let vertices = gl.create_buffer().ok()?;
+        let indices = gl.create_buffer().ok()?;
+        gl.bind_vertex_array(Some(vertex_array));
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vertices));
+        gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(indices));
+        let stride = std::mem::size_of::<Vertex>() as i32;
+        gl.vertex_attrib_pointer_f32(a_pos, 2, glow::FLOAT, false, stride, 0);
+        gl.enable_vertex_attrib_array(a_pos);
+        gl.vertex_attrib_pointer_f32(a_offset, 2, glow::FLOAT, false, stride, 8);
+        gl.enable_vertex_attrib_array(a_offset);
+        gl.vertex_attrib_pointer_f32(a_srgba, 4, glow::UNSIGNED_BYTE, false, stride, 16);
+        gl.enable_vertex_attrib_array(a_srgba);
+        gl.bind_vertex_array(None);
+
+        Some(GpuLayer {
+            vertex_array,
+            vertices,
+            indices,
+            vertex_capacity: 0,
+            index_capacity: 0,
+            index_count: 0,
+        })
+    }
+
+    // sends what changed in `layer` since last time, or all of it if it
+    // outgrew the buffers. The vertex array has to be bound.
+    unsafe fn upload(&mut self, gl: &glow::Context, layer: &mut Layer) {
+        let dirty = std::mem::take(&mut layer.dirty);
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vertices));
+        if dirty.all
+            || layer.vertices.len() > self.vertex_capacity
+            || layer.indices.len() > self.index_capacity
+        {
+            // with the same room to grow as the vectors, so adding a few
+            // leaves doesn't mean uploading all of them again
+            self.vertex_capacity = layer.vertices.capacity();
+            self.index_capacity = layer.indices.capacity();
+            for (target, size, data) in [
+                (
+                    glow::ARRAY_BUFFER,
+                    self.vertex_capacity * std::mem::size_of::<Vertex>(),
+                    bytemuck::cast_slice(&layer.vertices),
+                ),
+                (
+                    glow::ELEMENT_ARRAY_BUFFER,
+                    self.index_capacity * std::mem::size_of::<u32>(),
+                    bytemuck::cast_slice(&layer.indices),
+                ),
+            ] {
+                gl.buffer_data_size(target, size as i32, glow::DYNAMIC_DRAW);
+                gl.buffer_sub_data_u8_slice(target, 0, data);
+            }
+        } else {
+            for range in dirty.vertices {
+                let offset = range.start * std::mem::size_of::<Vertex>();
+                let data = bytemuck::cast_slice(&layer.vertices[range]);
+                gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, offset as i32, data);
+            }
+            for range in dirty.indices {
+                let offset = range.start * std::mem::size_of::<u32>();
+                let data = bytemuck::cast_slice(&layer.indices[range]);
+                gl.buffer_sub_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, offset as i32, data);
+            }
+        }
+        self.index_count = layer.indices.len() as i32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    // each leaf's triangles, wherever they ended up in the layers
+    fn leaf_vertices(mesh: &LeafMesh) -> Vec<(bsp::BspKey, Vec<Vertex>, Vec<Vertex>)> {
+        let layers = mesh.layers.lock().unwrap();
+        let span = |layer: &Layer, span: &Option<Span>| match span {
+            Some(span) => layer.vertices[span.vertices.clone()].to_vec(),
+            None => Vec::new(),
+        };
+        let mut leaves: Vec<_> = mesh
+            .slots
+            .iter()
+            .map(|(key, slot)| {
+                (
+                    key,
+                    span(&layers.outlines, &slot.outline),
+                    span(&layers.fills, &slot.fill),
+                )
+            })
+            .collect();
+        leaves.sort_by_key(|&(key, _, _)| key);
+        leaves
+    }
+
+    #[test]
+    fn patches_match_a_rebuild() {
+        let style = Style {
+            outline_width: 0.002,
+            ..Style::default()
+        };
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(5);
+        let mut bsp = bsp::Bsp::new(Oklab::new(0.5, 0.0, 0.0));
+        let mut regions = RegionCache::new(&bsp, Polygon::new_rect(Vec2::ZERO, Vec2::ONE));
+        let mut mesh = LeafMesh::new(None);
+        mesh.update(&regions, &bsp, &style);
+
+        for step in 0..300 {
+            let point = Vec2::new(rng.gen(), rng.gen());
+            let changed = match step % 5 {
+                0 | 1 => {
+                    let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+                    let normal = Vec2::new(angle.cos(), angle.sin());
+                    let color = Oklab::new(rng.gen(), 0.0, 0.0);
+                    Changed::Subtree(bsp.split_at_point(point, normal, color))
+                }
+                2 => match bsp.unsplit_at_point(point) {
+                    Some(key) => Changed::Subtree(key),
+                    None => continue,
+                },
+                _ => {
+                    *bsp.get_at_point_mut(point) = Oklab::new(rng.gen(), 0.1, 0.0);
+                    Changed::Colors
+                }
+            };
+            if let Changed::Subtree(key) = changed {
+                regions.update_subtree(&bsp, key);
+            }
+            mesh.changed(&changed);
+            mesh.update(&regions, &bsp, &style);
+
+            let mut rebuilt = LeafMesh::new(None);
+            rebuilt.update(&regions, &bsp, &style);
+            assert_eq!(
+                leaf_vertices(&mesh),
+                leaf_vertices(&rebuilt),
+                "after step {}",
+                step
+            );
+
+            // and nothing else still draws
+            let live = |mesh: &LeafMesh| {
+                let layers = mesh.layers.lock().unwrap();
+                layers.live()
+            };
+            assert_eq!(live(&mesh), live(&rebuilt));
+        }
+    }
+}
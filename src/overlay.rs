@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use eframe::egui;
+use egui::{Color32, Stroke};
+use glam::Vec2;
+use palette::Oklab;
+
+use wrong_track::{bsp, in_gamut, BspNode, RegionCache};
+
+use crate::view::View;
+
+/// The tree's structure drawn over the picture: every inode's cut line,
+/// clipped to that inode's own region, colored and sized by depth.
+///
+/// Like [`crate::mesh::LeafMesh`], the lines are only worked out again when
+/// the tree changes.
+#[derive(Default)]
+pub struct SplitOverlay {
+    // deepest first, so shallow cuts end up drawn on top
+    lines: Vec<CutLine>,
+    built_for: Option<u64>,
+}
+
+struct CutLine {
+    key: bsp::BspKey,
+    ends: (Vec2, Vec2),
+    depth: usize,
+}
+
+impl SplitOverlay {
+    /// Draws the lines for `revision` of the tree. The splits above
+    /// `hovered` get drawn again, heavier, on top of the rest.
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint(
+        &mut self,
+        painter: &egui::Painter,
+        view: View,
+        rect: egui::Rect,
+        revision: u64,
+        bsp: &bsp::Bsp<Oklab>,
+        regions: &RegionCache,
+        hovered: Option<bsp::BspKey>,
+    ) {
+        if self.built_for != Some(revision) {
+            self.rebuild(bsp, regions);
+            self.built_for = Some(revision);
+        }
+
+        let to_screen = |(a, b): (Vec2, Vec2)| {
+            [view.canvas_to_screen(a, rect), view.canvas_to_screen(b, rect)]
+        };
+
+        for line in &self.lines {
+            painter.line_segment(
+                to_screen(line.ends),
+                Stroke::new(width_for(line.depth), color_for(line.depth)),
+            );
+        }
+
+        let emphasized: HashSet<bsp::BspKey> = match hovered {
+            Some(key) => bsp.ancestors(key).collect(),
+            None => return,
+        };
+        for line in self.lines.iter().filter(|line| emphasized.contains(&line.key)) {
+            let width = width_for(line.depth) + 2.0;
+            painter.line_segment(to_screen(line.ends), Stroke::new(width + 2.0, Color32::BLACK));
+            painter.line_segment(to_screen(line.ends), Stroke::new(width, color_for(line.depth)));
+        }
+    }
+
+    fn rebuild(&mut self, bsp: &bsp::Bsp<Oklab>, regions: &RegionCache) {
+        self.lines.clear();
+
+        let mut stack = vec![(bsp.root_key(), 0)];
+        while let Some((key, depth)) = stack.pop() {
+            let inode = match bsp.get(key) {
+                Some(BspNode::Inode(inode)) => inode,
+                _ => continue,
+            };
+            stack.push((inode.le, depth + 1));
+            stack.push((inode.gt, depth + 1));
+
+            if let Some(ends) = regions.region(key).and_then(|r| r.cut_line(&inode.plane)) {
+                self.lines.push(CutLine { key, ends, depth });
+            }
+        }
+
+        self.lines.sort_by_key(|line| std::cmp::Reverse(line.depth));
+    }
+}
+
+/// Outlines around the leaves whose colors sRGB can't show, which every
+/// picture shows with less chroma than they have. Like [`SplitOverlay`],
+/// they're only looked for again when the tree changes.
+#[derive(Default)]
+pub struct GamutOverlay {
+    leaves: Vec<bsp::BspKey>,
+    built_for: Option<u64>,
+}
+
+impl GamutOverlay {
+    /// Draws the outlines for `revision` of the tree.
+    pub fn paint(
+        &mut self,
+        painter: &egui::Painter,
+        view: View,
+        rect: egui::Rect,
+        revision: u64,
+        bsp: &bsp::Bsp<Oklab>,
+        regions: &RegionCache,
+    ) {
+        if self.built_for != Some(revision) {
+            self.leaves = regions
+                .leaves()
+                .filter(|&(key, _)| bsp.leaf_value(key).is_some_and(|&color| !in_gamut(color)))
+                .map(|(key, _)| key)
+                .collect();
+            self.built_for = Some(revision);
+        }
+
+        for region in self.leaves.iter().filter_map(|&key| regions.region(key)) {
+            let points: Vec<egui::Pos2> = region
+                .vertices
+                .iter()
+                .map(|&v| view.canvas_to_screen(v, rect))
+                .collect();
+            // dark under bright, so it shows up on any color
+            let magenta = Color32::from_rgb(255, 0, 255);
+            painter.add(egui::Shape::closed_line(points.clone(), Stroke::new(3.0, Color32::BLACK)));
+            painter.add(egui::Shape::closed_line(points, Stroke::new(1.5, magenta)));
+        }
+    }
+}
+
+fn width_for(depth: usize) -> f32 {
+    (3.0 * 0.85f32.powi(depth as i32)).max(0.75)
+}
+
+// steps around the hue wheel, so neighbouring depths are easy to tell apart
+fn color_for(depth: usize) -> Color32 {
+    let hue = (depth as f32 * 0.13).fract();
+    egui::epaint::Hsva::new(hue, 0.8, 1.0, 1.0).into()
+}
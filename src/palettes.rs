@@ -0,0 +1,484 @@
+use palette::chromatic_adaptation::AdaptInto;
+use palette::white_point::{D50, D65};
+use palette::{IntoColor, Lab, Oklab, Srgb, Xyz};
+use serde::{Deserialize, Serialize};
+
+/// File extensions [`Palette::import`] reads: GIMP, Adobe swatch exchange,
+/// and plain lists of hex colors.
+pub const EXTENSIONS: &[&str] = &["gpl", "ase", "hex", "txt"];
+/// The heaviest a color can be, next to the default of 1.
+pub const MAX_WEIGHT: f32 = 10.0;
+
+/// Whether `file_name` has one of the [`EXTENSIONS`].
+pub fn is_palette_file(file_name: &str) -> bool {
+    std::path::Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// A named list of colors to pick from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<Srgb>,
+    /// How likely each color is to come up next to the others. Colors past
+    /// the end weigh 1.
+    #[serde(default)]
+    pub weights: Vec<f32>,
+}
+
+impl Palette {
+    /// Every color weighing the same.
+    pub fn new(name: String, colors: Vec<Srgb>) -> Palette {
+        Palette {
+            name,
+            weights: vec![1.0; colors.len()],
+            colors,
+        }
+    }
+
+    /// The palettes that come with the app, the first of them the default.
+    pub fn builtin() -> Vec<Palette> {
+        BUILTIN
+            .iter()
+            .map(|&(name, colors)| {
+                let colors = colors
+                    .iter()
+                    .map(|&rgb| {
+                        let [r, g, b] = [rgb >> 16, rgb >> 8, rgb].map(|x| (x & 0xff) as u8);
+                        Srgb::new(r, g, b).into_format()
+                    })
+                    .collect();
+                Palette::new(name.to_owned(), colors)
+            })
+            .collect()
+    }
+
+    /// Reads a palette in any of the formats of [`EXTENSIONS`], going by
+    /// what's in the file rather than its name. The name is only used for
+    /// formats that don't name the palette themselves.
+    pub fn import(file_name: &str, bytes: &[u8]) -> Result<Palette, PaletteError> {
+        let name = std::path::Path::new(file_name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or("Imported")
+            .to_owned();
+
+        let palette = if bytes.starts_with(b"ASEF") {
+            Palette::from_ase(name, bytes)?
+        } else {
+            let text = std::str::from_utf8(bytes).map_err(|_| PaletteError::UnknownFormat)?;
+            let text = text.trim_start_matches('\u{feff}');
+            if text.trim_start().starts_with("GIMP Palette") {
+                Palette::from_gpl(name, text)?
+            } else {
+                Palette::from_hex_list(name, text)?
+            }
+        };
+
+        if palette.colors.is_empty() {
+            return Err(PaletteError::Empty);
+        }
+        Ok(palette)
+    }
+
+    // "GIMP Palette", then an optional name and column count, then one
+    // "red green blue name" line per color
+    fn from_gpl(name: String, text: &str) -> Result<Palette, PaletteError> {
+        let mut palette = Palette::new(name, Vec::new());
+        for (i, line) in text
+            .lines()
+            .enumerate()
+            .skip_while(|(_, line)| line.trim().is_empty())
+            .skip(1)
+        {
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix("Name:") {
+                palette.name = name.trim().to_owned();
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+                continue;
+            }
+
+            let mut channels = line.split_whitespace().map(|c| c.parse::<u8>().ok());
+            match [channels.next(), channels.next(), channels.next()] {
+                [Some(Some(r)), Some(Some(g)), Some(Some(b))] => {
+                    palette.push(Srgb::new(r, g, b).into_format());
+                }
+                _ => return Err(PaletteError::BadLine(i + 1)),
+            }
+        }
+        Ok(palette)
+    }
+
+    // one color per line, maybe followed by a name; lines that don't start
+    // with a color are comments if they start like one
+    fn from_hex_list(name: String, text: &str) -> Result<Palette, PaletteError> {
+        let mut palette = Palette::new(name, Vec::new());
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            // anything that reads as a color is one, whatever follows it
+            let first = line.split_whitespace().next().unwrap_or("");
+            match parse_hex(first) {
+                Some(color) => palette.push(color),
+                None if line.is_empty()
+                    || line.starts_with(['#', ';'])
+                    || line.starts_with("//") => {}
+                None => return Err(PaletteError::BadLine(i + 1)),
+            }
+        }
+        Ok(palette)
+    }
+
+    // big-endian blocks after a 12 byte header ending in how many there are:
+    // group starts and ends, which we flatten, and color entries, each a
+    // UTF-16 name, a color model, its channels as floats and a color type
+    fn from_ase(name: String, bytes: &[u8]) -> Result<Palette, PaletteError> {
+        let mut reader = Reader(bytes);
+        reader.take(8)?;
+        let blocks = reader.u32()?;
+        let mut palette = Palette::new(name, Vec::new());
+
+        for _ in 0..blocks {
+            let kind = reader.u16()?;
+            let length = reader.u32()? as usize;
+            let mut block = Reader(reader.take(length)?);
+            if kind != 0x0001 {
+                continue;
+            }
+
+            let name_units = block.u16()? as usize;
+            block.take(name_units * 2)?;
+            let model = block.take(4)?;
+            let mut channels =
+                |n: usize| (0..n).map(|_| block.f32()).collect::<Result<Vec<_>, _>>();
+            let color = match model {
+                b"RGB " => {
+                    let c = channels(3)?;
+                    Srgb::new(c[0], c[1], c[2])
+                }
+                b"Gray" => {
+                    let c = channels(1)?;
+                    Srgb::new(c[0], c[0], c[0])
+                }
+                // there's no knowing the press, so a naive conversion
+                b"CMYK" => {
+                    let c = channels(4)?;
+                    let k = 1.0 - c[3];
+                    Srgb::new((1.0 - c[0]) * k, (1.0 - c[1]) * k, (1.0 - c[2]) * k)
+                }
+                // lightness comes as a fraction
+                b"LAB " => {
+                    let c = channels(3)?;
+                    let xyz: Xyz<D50> = Lab::<D50>::with_wp(c[0] * 100.0, c[1], c[2]).into_color();
+                    let xyz: Xyz<D65> = xyz.adapt_into();
+                    xyz.into_color()
+                }
+                _ => return Err(PaletteError::BadAse),
+            };
+            palette.push(clamp(color));
+        }
+        Ok(palette)
+    }
+
+    /// How likely color `i` is to come up, next to the others.
+    pub fn weight(&self, i: usize) -> f32 {
+        self.weights.get(i).copied().unwrap_or(1.0)
+    }
+
+    pub fn push(&mut self, color: Srgb) {
+        self.weights.resize(self.colors.len(), 1.0);
+        self.colors.push(color);
+        self.weights.push(1.0);
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        self.colors.remove(i);
+        if i < self.weights.len() {
+            self.weights.remove(i);
+        }
+    }
+
+    /// The colors as the tree stores them.
+    pub fn oklab(&self) -> Vec<Oklab> {
+        self.colors
+            .iter()
+            .map(|&color| wrong_track::from_srgb(color))
+            .collect()
+    }
+
+    /// The colors as the tree stores them, with their weights.
+    pub fn weighted(&self) -> Vec<(Oklab, f32)> {
+        self.oklab()
+            .into_iter()
+            .enumerate()
+            .map(|(i, color)| (color, self.weight(i)))
+            .collect()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::builtin().swap_remove(0)
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    UnknownFormat,
+    /// A line (counting from 1) of a text palette that isn't a color.
+    BadLine(usize),
+    BadAse,
+    Empty,
+}
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaletteError::UnknownFormat => write!(
+                f,
+                "not a palette (use .gpl, .ase, or a list of colors like #ff8800)"
+            ),
+            PaletteError::BadLine(line) => {
+                write!(f, "palette line {} is not a color", line)
+            }
+            PaletteError::BadAse => write!(f, "swatch file is corrupt"),
+            PaletteError::Empty => write!(f, "palette has no colors"),
+        }
+    }
+}
+impl std::error::Error for PaletteError {}
+
+// #rgb, #rrggbb, or either without the #
+fn parse_hex(s: &str) -> Option<Srgb> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize, n: usize| u8::from_str_radix(&hex[i * n..(i + 1) * n], 16).ok();
+    let [r, g, b] = match hex.len() {
+        3 => [channel(0, 1)?, channel(1, 1)?, channel(2, 1)?].map(|c| c * 17),
+        6 => [channel(0, 2)?, channel(1, 2)?, channel(2, 2)?],
+        _ => return None,
+    };
+    Some(Srgb::new(r, g, b).into_format())
+}
+
+fn clamp(color: Srgb) -> Srgb {
+    let unit = |x: f32| {
+        if x.is_finite() {
+            x.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    };
+    Srgb::new(unit(color.red), unit(color.green), unit(color.blue))
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], PaletteError> {
+        if n > self.0.len() {
+            return Err(PaletteError::BadAse);
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, PaletteError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, PaletteError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, PaletteError> {
+        Ok(f32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+// five colors each, which is always enough to keep neighbours apart
+const BUILTIN: &[(&str, &[u32])] = &[
+    (
+        "Primaries",
+        &[0xd40920, 0x1356a2, 0xf7d842, 0xf2f2f2, 0x1c1c1c],
+    ),
+    ("Atlas", &[0xe8c872, 0xa8c686, 0xd99a8b, 0x9fb8d0, 0xc6a8cf]),
+    (
+        "Pastel",
+        &[0xffadad, 0xfdffb6, 0xcaffbf, 0x9bf6ff, 0xbdb2ff],
+    ),
+    ("Earth", &[0x264653, 0x2a9d8f, 0xe9c46a, 0xf4a261, 0xe76f51]),
+    ("Greys", &[0x1c1c1c, 0x555555, 0x8e8e8e, 0xc6c6c6, 0xf2f2f2]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb8(color: Srgb) -> [u8; 3] {
+        let color: Srgb<u8> = color.into_format();
+        [color.red, color.green, color.blue]
+    }
+
+    fn colors8(palette: &Palette) -> Vec<[u8; 3]> {
+        palette.colors.iter().map(|&color| rgb8(color)).collect()
+    }
+
+    // a swatch file holding `blocks`, each a type and a body
+    fn ase(blocks: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = b"ASEF".to_vec();
+        bytes.extend(1u16.to_be_bytes());
+        bytes.extend(0u16.to_be_bytes());
+        bytes.extend((blocks.len() as u32).to_be_bytes());
+        for (kind, body) in blocks {
+            bytes.extend(kind.to_be_bytes());
+            bytes.extend((body.len() as u32).to_be_bytes());
+            bytes.extend(body);
+        }
+        bytes
+    }
+
+    fn ase_color(name: &str, model: &[u8; 4], channels: &[f32]) -> (u16, Vec<u8>) {
+        let name: Vec<u16> = name.encode_utf16().chain([0]).collect();
+        let mut body = (name.len() as u16).to_be_bytes().to_vec();
+        body.extend(name.iter().flat_map(|unit| unit.to_be_bytes()));
+        body.extend(model);
+        body.extend(channels.iter().flat_map(|c| c.to_be_bytes()));
+        body.extend(2u16.to_be_bytes());
+        (0x0001, body)
+    }
+
+    #[test]
+    fn parse_hex_forms() {
+        assert_eq!(parse_hex("#ff8800").map(rgb8), Some([255, 136, 0]));
+        assert_eq!(parse_hex("FF8800").map(rgb8), Some([255, 136, 0]));
+        assert_eq!(parse_hex("#f80").map(rgb8), Some([255, 136, 0]));
+        assert_eq!(parse_hex("fff").map(rgb8), Some([255, 255, 255]));
+        for bad in ["", "#", "#ff", "#ff88", "#ff88001", "#gg8800", "#ff 880"] {
+            assert_eq!(parse_hex(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn gpl() {
+        let text = "GIMP Palette\nName: Sunset\nColumns: 4\n# a comment\n\n255 136   0\torange\n  0   0 255\n";
+        let palette = Palette::import("file.gpl", text.as_bytes()).unwrap();
+        assert_eq!(palette.name, "Sunset");
+        assert_eq!(colors8(&palette), [[255, 136, 0], [0, 0, 255]]);
+        assert_eq!(palette.weights, [1.0, 1.0]);
+
+        let bad = "GIMP Palette\n255 0 0\n255 zero 0\n";
+        assert!(matches!(
+            Palette::import("bad.gpl", bad.as_bytes()),
+            Err(PaletteError::BadLine(3))
+        ));
+        assert!(matches!(
+            Palette::import("empty.gpl", b"GIMP Palette\nName: Nothing\n"),
+            Err(PaletteError::Empty)
+        ));
+    }
+
+    #[test]
+    fn hex_list() {
+        let text =
+            "\u{feff}# my colors\n#ff8800 orange\n\n0000ff\n; also a comment\n// and this\n#f0f\n";
+        let palette = Palette::import("mine.hex", text.as_bytes()).unwrap();
+        assert_eq!(palette.name, "mine");
+        assert_eq!(
+            colors8(&palette),
+            [[255, 136, 0], [0, 0, 255], [255, 0, 255]]
+        );
+
+        assert!(matches!(
+            Palette::import("bad.txt", b"#ff8800\nnot a color\n"),
+            Err(PaletteError::BadLine(2))
+        ));
+        assert!(matches!(
+            Palette::import("bad.txt", b"ff8800,orange\n"),
+            Err(PaletteError::BadLine(1))
+        ));
+    }
+
+    #[test]
+    fn hex_list_names_that_look_like_comments() {
+        let text = "#fff White\n#eee light grey\n# ff0000\n#TODO\n#abcdef light blue\n";
+        let palette = Palette::import("list.txt", text.as_bytes()).unwrap();
+        assert_eq!(
+            colors8(&palette),
+            [[255, 255, 255], [238, 238, 238], [171, 205, 239]]
+        );
+    }
+
+    #[test]
+    fn ase_models_and_groups() {
+        let bytes = ase(&[
+            (0xc001, vec![0, 2, 0, b'G', 0, 0]),
+            ase_color("red", b"RGB ", &[1.0, 0.0, 0.0]),
+            ase_color("grey", b"Gray", &[0.5]),
+            ase_color("cyan", b"CMYK", &[1.0, 0.0, 0.0, 0.0]),
+            (0xc002, Vec::new()),
+            ase_color("white", b"LAB ", &[1.0, 0.0, 0.0]),
+            ase_color("over", b"RGB ", &[2.0, -1.0, f32::NAN]),
+        ]);
+        let palette = Palette::import("swatches.ase", &bytes).unwrap();
+        assert_eq!(palette.name, "swatches");
+        assert_eq!(
+            colors8(&palette),
+            [
+                [255, 0, 0],
+                [128, 128, 128],
+                [0, 255, 255],
+                [255, 255, 255],
+                [255, 0, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn ase_corrupt() {
+        let good = ase(&[ase_color("red", b"RGB ", &[1.0, 0.0, 0.0])]);
+
+        // cut off anywhere after the magic
+        for len in 4..good.len() {
+            assert!(
+                matches!(
+                    Palette::import("cut.ase", &good[..len]),
+                    Err(PaletteError::BadAse)
+                ),
+                "cut to {}",
+                len
+            );
+        }
+
+        // a block claiming more than there is
+        let mut long = good.clone();
+        long[16..20].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(matches!(
+            Palette::import("long.ase", &long),
+            Err(PaletteError::BadAse)
+        ));
+
+        // a block too short for its color
+        let (kind, mut body) = ase_color("red", b"RGB ", &[1.0, 0.0, 0.0]);
+        body.truncate(body.len() - 6);
+        assert!(matches!(
+            Palette::import("short.ase", &ase(&[(kind, body)])),
+            Err(PaletteError::BadAse)
+        ));
+
+        assert!(matches!(
+            Palette::import("odd.ase", &ase(&[ase_color("x", b"HSV ", &[0.0; 3])])),
+            Err(PaletteError::BadAse)
+        ));
+        assert!(matches!(
+            Palette::import("none.ase", &ase(&[])),
+            Err(PaletteError::Empty)
+        ));
+    }
+}
@@ -0,0 +1,408 @@
+use palette::Oklab;
+use serde::{Deserialize, Serialize};
+
+use wrong_track::{bsp, SplitConstraints, Style};
+
+use crate::generate::{ColorSource, PaletteMode};
+use crate::palettes::{Palette, MAX_WEIGHT};
+
+/// Written at the top of every project file so we can tell our files apart
+/// from random JSON.
+pub const FORMAT: &str = "wrongtrack";
+/// Bump whenever the layout of `Project` changes in a way old code can't read.
+pub const VERSION: u32 = 1;
+pub const EXTENSION: &str = "wrongtrack";
+/// The most pixels a document can be on either side.
+pub const MAX_DOCUMENT_SIZE: u32 = 65535;
+/// The widest outline or gutter, as a fraction of the document's long side.
+pub const MAX_STYLE_LENGTH: f32 = 0.1;
+
+/// Everything needed to reopen a composition and keep editing it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub settings: Settings,
+    pub bsp: bsp::Bsp<Oklab>,
+}
+
+/// The side panel knobs of `MyEguiApp`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub normal_randomness: f32,
+    pub color_randomness: f32,
+    pub num_color_samples: usize,
+    #[serde(default)]
+    pub color_source: ColorSource,
+
+    pub override_color_enabled: bool,
+    /// hue, saturation, value, alpha
+    pub override_color: [f32; 4],
+
+    // files from before seeds existed just get a fresh one
+    #[serde(default = "rand::random")]
+    pub seed: u64,
+    /// How far along the random sequence for `seed` the picture got, so
+    /// the same edits after loading give the same picture as they would
+    /// have before saving. Files from before this start over. The generator
+    /// counts in `u128`, which JSON can't hold, but no picture gets past
+    /// the first `u64` of it.
+    #[serde(default)]
+    pub rng_position: u64,
+
+    /// Width and height in pixels. Files from before this existed were all
+    /// square.
+    #[serde(default = "default_document_size")]
+    pub document_size: [u32; 2],
+
+    #[serde(default)]
+    pub style: Style,
+
+    /// Limits on splits. Areas are in canvas units squared.
+    #[serde(default)]
+    pub split_constraints: SplitConstraints,
+
+    /// Whether every split is pruned right away (see [`bsp::Bsp::prune`]).
+    #[serde(default)]
+    pub auto_prune: bool,
+    /// Smallest area pruning keeps, in canvas units squared.
+    #[serde(default)]
+    pub prune_min_area: f32,
+
+    /// The palette new colors and RECOLOR pick from.
+    #[serde(default)]
+    pub palette: Palette,
+    /// Every palette to choose from, which may or may not include `palette`.
+    #[serde(default = "Palette::builtin")]
+    pub palettes: Vec<Palette>,
+    #[serde(default)]
+    pub palette_mode: PaletteMode,
+    #[serde(default)]
+    pub recolor_seed: u64,
+    /// Whether new leaves avoid their neighbours' colors.
+    #[serde(default)]
+    pub keep_apart: bool,
+}
+
+pub fn default_document_size() -> [u32; 2] {
+    [2048, 2048]
+}
+
+#[derive(Serialize)]
+struct FileRef<'a> {
+    format: &'a str,
+    version: u32,
+    #[serde(flatten)]
+    project: &'a Project,
+}
+
+// read first, on its own, so a file from the future fails on the version
+// instead of on whatever field it happens to have changed
+#[derive(Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct File {
+    #[serde(flatten)]
+    project: Project,
+}
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(std::io::Error),
+    NotAProject,
+    UnsupportedVersion(u32),
+    Malformed(serde_json::Error),
+}
+impl std::fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectError::Io(e) => write!(f, "could not access project file: {}", e),
+            ProjectError::NotAProject => write!(f, "not a wrong!track! project file"),
+            ProjectError::UnsupportedVersion(v) => write!(
+                f,
+                "project was saved by a newer version (format {}, we support up to {})",
+                v, VERSION
+            ),
+            ProjectError::Malformed(e) => write!(f, "project file is corrupt: {}", e),
+        }
+    }
+}
+impl std::error::Error for ProjectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProjectError::Io(e) => Some(e),
+            ProjectError::Malformed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<std::io::Error> for ProjectError {
+    fn from(e: std::io::Error) -> Self {
+        ProjectError::Io(e)
+    }
+}
+
+impl Project {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let file = FileRef {
+            format: FORMAT,
+            version: VERSION,
+            project: self,
+        };
+
+        // nothing in here can fail to serialize: no maps with non-string keys,
+        // and NaNs just come out as null (and get rejected on load)
+        serde_json::to_vec(&file).expect("project serialization failed")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Project, ProjectError> {
+        let header: Header = serde_json::from_slice(bytes).map_err(|e| {
+            if e.is_data() {
+                // valid JSON, just not ours
+                ProjectError::NotAProject
+            } else {
+                ProjectError::Malformed(e)
+            }
+        })?;
+        if header.format != FORMAT {
+            return Err(ProjectError::NotAProject);
+        }
+        if header.version > VERSION {
+            return Err(ProjectError::UnsupportedVersion(header.version));
+        }
+
+        let file: File = serde_json::from_slice(bytes).map_err(ProjectError::Malformed)?;
+        let mut project = file.project;
+        project.settings.sanitize();
+
+        Ok(project)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &std::path::Path) -> Result<(), ProjectError> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &std::path::Path) -> Result<Project, ProjectError> {
+        Project::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+impl Settings {
+    // keep hand-edited files inside the ranges the UI allows
+    fn sanitize(&mut self) {
+        let unit = |x: f32| if x.is_finite() { x.clamp(0.0, 1.0) } else { 0.5 };
+
+        self.normal_randomness = unit(self.normal_randomness);
+        self.color_randomness = unit(self.color_randomness);
+        self.num_color_samples = self.num_color_samples.clamp(1, 64);
+        for c in &mut self.override_color {
+            *c = unit(*c);
+        }
+        for d in &mut self.document_size {
+            *d = (*d).clamp(1, MAX_DOCUMENT_SIZE);
+        }
+
+        // the canvas is at most 1 by 1, and aspect ratios are at most 1 too
+        let fraction = |x: f32| if x.is_finite() { x.clamp(0.0, 1.0) } else { 0.0 };
+        self.prune_min_area = fraction(self.prune_min_area);
+        self.split_constraints.min_area = fraction(self.split_constraints.min_area);
+        self.split_constraints.min_aspect_ratio =
+            fraction(self.split_constraints.min_aspect_ratio);
+
+        for palette in std::iter::once(&mut self.palette).chain(&mut self.palettes) {
+            for weight in &mut palette.weights {
+                *weight = if weight.is_finite() { weight.clamp(0.0, MAX_WEIGHT) } else { 1.0 };
+            }
+        }
+
+        let length = |x: f32| if x.is_finite() { x.clamp(0.0, MAX_STYLE_LENGTH) } else { 0.0 };
+        let style = &mut self.style;
+        style.outline_width = length(style.outline_width);
+        style.gutter = length(style.gutter);
+        let colors = std::iter::once(&mut style.outline_color)
+            .chain(&mut style.gutter_color)
+            .chain(&mut self.palette.colors)
+            .chain(self.palettes.iter_mut().flat_map(|p| &mut p.colors));
+        for color in colors {
+            color.red = unit(color.red);
+            color.green = unit(color.green);
+            color.blue = unit(color.blue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn project() -> Project {
+        let mut bsp = bsp::Bsp::new(Oklab::new(0.2, 0.0, 0.0));
+        bsp.split_at_point(Vec2::splat(0.5), Vec2::X, Oklab::new(0.8, 0.1, -0.1));
+        bsp.split_at_point(Vec2::new(0.75, 0.5), Vec2::Y, Oklab::new(0.5, -0.1, 0.1));
+
+        let settings = Settings {
+            normal_randomness: 0.25,
+            color_randomness: 0.75,
+            num_color_samples: 8,
+            color_source: ColorSource::default(),
+            override_color_enabled: true,
+            override_color: [0.1, 0.2, 0.3, 1.0],
+            seed: 42,
+            rng_position: u64::MAX - 1,
+            document_size: [1920, 1080],
+            style: Style {
+                outline_width: 0.01,
+                gutter: 0.005,
+                ..Style::default()
+            },
+            split_constraints: SplitConstraints::default(),
+            auto_prune: true,
+            prune_min_area: 1e-6,
+            palette: Palette::default(),
+            palettes: Palette::builtin(),
+            palette_mode: PaletteMode::default(),
+            recolor_seed: 7,
+            keep_apart: true,
+        };
+        Project { settings, bsp }
+    }
+
+    fn to_json(project: &Project) -> Value {
+        serde_json::from_slice(&project.to_bytes()).unwrap()
+    }
+
+    fn from_json(value: &Value) -> Result<Project, ProjectError> {
+        Project::from_bytes(&serde_json::to_vec(value).unwrap())
+    }
+
+    // the slot of the first inode in the tree's node list
+    fn first_inode(value: &mut Value) -> &mut Value {
+        value["bsp"]["nodes"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .find(|slot| slot["value"].get("Inode").is_some())
+            .map(|slot| &mut slot["value"]["Inode"])
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let project = project();
+        let loaded = Project::from_bytes(&project.to_bytes()).unwrap();
+        assert_eq!(loaded.to_bytes(), project.to_bytes());
+
+        assert_eq!(loaded.settings.seed, 42);
+        assert_eq!(loaded.settings.rng_position, u64::MAX - 1);
+        assert_eq!(loaded.settings.document_size, [1920, 1080]);
+        assert_eq!(loaded.settings.num_color_samples, 8);
+        assert_eq!(loaded.settings.override_color, [0.1, 0.2, 0.3, 1.0]);
+        assert_eq!(loaded.bsp.len(), project.bsp.len());
+        for point in [Vec2::new(0.25, 0.5), Vec2::new(0.75, 0.25), Vec2::new(0.75, 0.75)] {
+            assert_eq!(loaded.bsp.get_at_point(point), project.bsp.get_at_point(point));
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut value = to_json(&project());
+        value["format"] = json!("something else");
+        assert!(matches!(from_json(&value), Err(ProjectError::NotAProject)));
+
+        assert!(matches!(
+            Project::from_bytes(br#"{"hello": "world"}"#),
+            Err(ProjectError::NotAProject)
+        ));
+        assert!(matches!(
+            Project::from_bytes(b"not json"),
+            Err(ProjectError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_future_versions() {
+        let mut value = to_json(&project());
+        value["version"] = json!(VERSION + 1);
+        // and whatever the future changed, which shouldn't matter
+        value["settings"] = json!("something new");
+        assert!(matches!(
+            from_json(&value),
+            Err(ProjectError::UnsupportedVersion(v)) if v == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupt_trees() {
+        let mut dangling = to_json(&project());
+        first_inode(&mut dangling)["gt"] = json!({"idx": 99, "version": 1});
+        assert!(matches!(from_json(&dangling), Err(ProjectError::Malformed(_))));
+
+        let mut twice = to_json(&project());
+        let inode = first_inode(&mut twice);
+        inode["gt"] = inode["le"].clone();
+        assert!(matches!(from_json(&twice), Err(ProjectError::Malformed(_))));
+
+        let mut degenerate = to_json(&project());
+        first_inode(&mut degenerate)["plane"]["normal"] = json!([0.0, 0.0]);
+        assert!(matches!(from_json(&degenerate), Err(ProjectError::Malformed(_))));
+    }
+
+    #[test]
+    fn sanitize_clamps() {
+        let mut settings = project().settings;
+        settings.normal_randomness = f32::NAN;
+        settings.color_randomness = 1e30;
+        settings.num_color_samples = 1_000_000;
+        settings.override_color = [f32::NAN, -1.0, 2.0, f32::INFINITY];
+        settings.document_size = [0, u32::MAX];
+        settings.style.outline_width = f32::NAN;
+        settings.style.gutter = 1e30;
+        settings.style.outline_color.red = f32::NAN;
+        settings.palette.colors[0].green = 1e30;
+        settings.palette.weights = vec![f32::NAN, 1e30, -1.0];
+        settings.palettes[1].weights[0] = f32::INFINITY;
+        settings.prune_min_area = f32::NAN;
+        settings.split_constraints.min_area = 1e30;
+        settings.split_constraints.min_aspect_ratio = -1.0;
+        settings.sanitize();
+
+        assert_eq!(settings.normal_randomness, 0.5);
+        assert_eq!(settings.color_randomness, 1.0);
+        assert_eq!(settings.num_color_samples, 64);
+        assert_eq!(settings.override_color, [0.5, 0.0, 1.0, 0.5]);
+        assert_eq!(settings.document_size, [1, MAX_DOCUMENT_SIZE]);
+        assert_eq!(settings.style.outline_width, 0.0);
+        assert_eq!(settings.style.gutter, MAX_STYLE_LENGTH);
+        assert_eq!(settings.style.outline_color.red, 0.5);
+        assert_eq!(settings.palette.colors[0].green, 1.0);
+        assert_eq!(settings.palette.weights, [1.0, MAX_WEIGHT, 0.0]);
+        assert_eq!(settings.palettes[1].weights[0], 1.0);
+        assert_eq!(settings.prune_min_area, 0.0);
+        assert_eq!(settings.split_constraints.min_area, 1.0);
+        assert_eq!(settings.split_constraints.min_aspect_ratio, 0.0);
+    }
+
+    #[test]
+    fn huge_values_load_clamped() {
+        let mut value = to_json(&project());
+        value["settings"]["color_randomness"] = json!(1e30);
+        value["settings"]["num_color_samples"] = json!(0);
+        value["settings"]["style"]["gutter"] = json!(1e30);
+        value["settings"]["prune_min_area"] = json!(-5.0);
+        value["settings"]["palette"]["weights"] = json!([1e30]);
+        let settings = from_json(&value).unwrap().settings;
+        assert_eq!(settings.color_randomness, 1.0);
+        assert_eq!(settings.num_color_samples, 1);
+        assert_eq!(settings.style.gutter, MAX_STYLE_LENGTH);
+        assert_eq!(settings.prune_min_area, 0.0);
+        assert_eq!(settings.palette.weights[0], MAX_WEIGHT);
+    }
+}
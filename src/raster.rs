@@ -0,0 +1,443 @@
+//! Rasterizing trees to bitmaps on the CPU.
+
+use std::io::Write;
+
+use glam::{vec2, Vec2};
+use palette::{LinSrgb, Srgb};
+
+use crate::bsp;
+use crate::color::to_srgb8;
+use crate::export::Fill;
+use crate::geom::{canvas_size, Plane, Polygon};
+use crate::style::Style;
+
+/// How many rows get rendered at once. Only one band's worth of pixels is
+/// ever held in memory, so huge images don't need a huge buffer.
+const BAND_HEIGHT: u32 = 64;
+
+/// A CPU rasterizer for the leaves of a tree.
+///
+/// Coverage is computed exactly (the signed-area accumulation trick from
+/// font-rs), and neighbouring leaves blend by how much of a pixel each of
+/// them covers, in linear light. Since leaves tile the canvas, pixels on a
+/// shared edge come out as a proper mix of both sides instead of showing
+/// a seam.
+///
+/// Outlines are a layer of their own under the fills, which are composited
+/// over them (and over the gutter color, if any).
+pub struct Rasterizer {
+    width: u32,
+    height: u32,
+    background: Option<LinSrgb>,
+    outlines: Vec<Shape>,
+    fills: Vec<Shape>,
+}
+
+// a polygon, already in pixel space
+struct Shape {
+    poly: Polygon,
+    min_y: f32,
+    max_y: f32,
+    color: LinSrgb,
+}
+impl Shape {
+    fn new(poly: &Polygon, scale: Vec2, color: Srgb) -> Option<Shape> {
+        if poly.vertices.len() < 3 {
+            return None;
+        }
+
+        let vertices: Vec<Vec2> = poly.vertices.iter().map(|&v| v * scale).collect();
+        let min_y = vertices.iter().map(|v| v.y).fold(f32::INFINITY, f32::min);
+        let max_y = vertices.iter().map(|v| v.y).fold(f32::NEG_INFINITY, f32::max);
+
+        Some(Shape {
+            poly: Polygon { vertices },
+            min_y,
+            max_y,
+            color: to_linear(color),
+        })
+    }
+}
+
+impl Rasterizer {
+    /// Prepares `bsp` for rendering at `width` x `height`, with the
+    /// [`canvas_size`] for that size filling the whole image.
+    pub fn new<T: Fill>(bsp: &bsp::Bsp<T>, width: u32, height: u32, style: &Style) -> Rasterizer {
+        let canvas = canvas_size(width, height);
+        let scale = vec2(width as f32, height as f32) / canvas;
+        let mut outlines = Vec::new();
+        let mut fills = Vec::new();
+
+        bsp.visit_leaf_polygons(
+            bsp.root_key(),
+            Polygon::new_rect(Vec2::ZERO, canvas),
+            &mut |leaf, poly| {
+                if poly.vertices.len() < 3 {
+                    return;
+                }
+
+                if let Some(outline) = style.outline_region(poly) {
+                    outlines.extend(Shape::new(&outline, scale, style.outline_color));
+                }
+                fills.extend(Shape::new(&style.fill_region(poly), scale, leaf.0.fill()));
+            },
+        );
+
+        Rasterizer {
+            width,
+            height,
+            background: style.gutter_color.map(to_linear),
+            outlines,
+            fills,
+        }
+    }
+
+    /// Renders rows `y0..y1` as non-premultiplied RGBA8 into `out`,
+    /// which must be exactly `(y1 - y0) * width * 4` bytes.
+    pub fn render_rows(&self, y0: u32, y1: u32, out: &mut [u8]) {
+        let width = self.width as usize;
+        let rows = (y1 - y0) as usize;
+        assert_eq!(out.len(), rows * width * 4);
+
+        let mut outlines = vec![[0.0f32; 4]; rows * width];
+        let mut fills = vec![[0.0f32; 4]; rows * width];
+        self.render_layer(&self.outlines, y0, y1, &mut outlines);
+        self.render_layer(&self.fills, y0, y1, &mut fills);
+
+        for ((outline, fill), out) in outlines.iter().zip(&fills).zip(out.chunks_exact_mut(4)) {
+            // premultiplied linear rgba, from the back to the front
+            let mut acc = match self.background {
+                Some(bg) => [bg.red, bg.green, bg.blue, 1.0],
+                None => [0.0; 4],
+            };
+            for layer in [outline, fill] {
+                let coverage = layer[3];
+                if coverage <= 0.0 {
+                    continue;
+                }
+                let alpha = coverage.min(1.0);
+                for c in 0..3 {
+                    acc[c] = layer[c] / coverage * alpha + acc[c] * (1.0 - alpha);
+                }
+                acc[3] = alpha + acc[3] * (1.0 - alpha);
+            }
+
+            let alpha = acc[3];
+            if alpha <= 0.0 {
+                out.copy_from_slice(&[0, 0, 0, 0]);
+                continue;
+            }
+
+            let color: Srgb =
+                Srgb::from_linear(LinSrgb::new(acc[0] / alpha, acc[1] / alpha, acc[2] / alpha));
+            out[..3].copy_from_slice(&to_srgb8(color));
+            // alpha in the same steps as the channels
+            let [alpha, _, _] = to_srgb8(Srgb::new(alpha, alpha, alpha));
+            out[3] = alpha;
+        }
+    }
+
+    // adds up, for every pixel in rows y0..y1, each shape's premultiplied
+    // linear rgb times its coverage, and the total coverage
+    fn render_layer(&self, shapes: &[Shape], y0: u32, y1: u32, pixels: &mut [[f32; 4]]) {
+        let width = self.width as usize;
+        let rows = (y1 - y0) as usize;
+        let mut accum = Vec::new();
+
+        let band_min = vec2(0.0, y0 as f32);
+        let band_max = vec2(self.width as f32, y1 as f32);
+
+        for shape in shapes {
+            if shape.max_y <= band_min.y || shape.min_y >= band_max.y {
+                continue;
+            }
+            let poly = match clip_to_rect(&shape.poly, band_min, band_max) {
+                Some(poly) => poly,
+                None => continue,
+            };
+
+            let min_x = poly.vertices.iter().map(|v| v.x).fold(f32::INFINITY, f32::min);
+            let max_x = poly.vertices.iter().map(|v| v.x).fold(f32::NEG_INFINITY, f32::max);
+            let x0 = min_x.max(0.0).floor() as usize;
+            let x1 = max_x.max(0.0).ceil() as usize;
+            // +2: edges write one past the pixel they end in
+            let stride = x1 - x0 + 2;
+
+            accum.clear();
+            accum.resize(stride * rows, 0.0f32);
+
+            // clipping can land a hair outside the band, which would index
+            // out of the buffer
+            let origin = vec2(x0 as f32, band_min.y);
+            let extent = vec2((x1 - x0) as f32, rows as f32);
+            let local = |v: Vec2| (v - origin).clamp(Vec2::ZERO, extent);
+
+            let mut prev = local(*poly.vertices.last().unwrap());
+            for &vert in &poly.vertices {
+                let vert = local(vert);
+                accumulate_edge(&mut accum, stride, rows, prev, vert);
+                prev = vert;
+            }
+
+            let color = shape.color;
+            for (row, line) in accum.chunks_exact(stride).enumerate() {
+                let mut acc = 0.0;
+                for (i, a) in line.iter().enumerate() {
+                    acc += a;
+                    let x = x0 + i;
+                    let coverage = acc.abs().min(1.0);
+                    if coverage <= 0.0 || x >= width {
+                        continue;
+                    }
+
+                    let px = &mut pixels[row * width + x];
+                    px[0] += color.red * coverage;
+                    px[1] += color.green * coverage;
+                    px[2] += color.blue * coverage;
+                    px[3] += coverage;
+                }
+            }
+        }
+    }
+
+    /// Encodes the image as a PNG, a band at a time. Fails before rendering
+    /// anything if either side is zero.
+    pub fn write_png<W: Write>(&self, w: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        let mut stream = writer.stream_writer()?;
+        let mut band = Vec::new();
+        for y0 in (0..self.height).step_by(BAND_HEIGHT as usize) {
+            let y1 = (y0 + BAND_HEIGHT).min(self.height);
+
+            band.resize((y1 - y0) as usize * self.width as usize * 4, 0);
+            self.render_rows(y0, y1, &mut band);
+            stream.write_all(&band)?;
+        }
+        stream.finish()?;
+        writer.finish()
+    }
+}
+
+/// Rasterizes `bsp` to a `width` x `height` PNG. Fails if either is zero,
+/// which a PNG can't be.
+pub fn save_png<T: Fill>(
+    bsp: &bsp::Bsp<T>,
+    width: u32,
+    height: u32,
+    style: &Style,
+) -> Result<Vec<u8>, png::EncodingError> {
+    let mut w = Vec::new();
+    Rasterizer::new(bsp, width, height, style).write_png(&mut w)?;
+    Ok(w)
+}
+
+fn to_linear(color: Srgb) -> LinSrgb {
+    Srgb::new(
+        color.red.clamp(0.0, 1.0),
+        color.green.clamp(0.0, 1.0),
+        color.blue.clamp(0.0, 1.0),
+    )
+    .into_linear()
+}
+
+fn clip_to_rect(poly: &Polygon, min: Vec2, max: Vec2) -> Option<Polygon> {
+    let planes = [
+        Plane {
+            normal: vec2(-1.0, 0.0),
+            distance: -min.x,
+        },
+        Plane {
+            normal: vec2(1.0, 0.0),
+            distance: max.x,
+        },
+        Plane {
+            normal: vec2(0.0, -1.0),
+            distance: -min.y,
+        },
+        Plane {
+            normal: vec2(0.0, 1.0),
+            distance: max.y,
+        },
+    ];
+
+    let mut poly = poly.clone();
+    for plane in &planes {
+        poly = poly.clip_against_plane(plane, true);
+        if poly.vertices.len() < 3 {
+            return None;
+        }
+    }
+    Some(poly)
+}
+
+/// Adds one edge's contribution to a signed-area accumulation buffer.
+///
+/// After a running sum along each row, every cell holds how much of that
+/// pixel lies inside the polygon (negated for the other winding). `from` and
+/// `to` are relative to the buffer and must lie within `0..=stride - 2`
+/// horizontally and `0..=rows` vertically.
+fn accumulate_edge(accum: &mut [f32], stride: usize, rows: usize, from: Vec2, to: Vec2) {
+    if (from.y - to.y).abs() <= f32::EPSILON {
+        return;
+    }
+
+    let (dir, p0, p1) = if from.y < to.y {
+        (1.0, from, to)
+    } else {
+        (-1.0, to, from)
+    };
+    let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+    // stepping along the edge accumulates error; don't let it wander
+    // past the ends, or (worse) below zero
+    let x_min = p0.x.min(p1.x);
+    let x_max = p0.x.max(p1.x);
+
+    let mut x = p0.x;
+    let y_start = p0.y.max(0.0) as usize;
+    let y_end = (p1.y.ceil() as usize).min(rows);
+    for y in y_start..y_end {
+        let line = &mut accum[y * stride..(y + 1) * stride];
+
+        let dy = ((y + 1) as f32).min(p1.y) - (y as f32).max(p0.y);
+        let x_next = (x + dxdy * dy).clamp(x_min, x_max);
+        let d = dy * dir;
+
+        let (xa, xb) = if x < x_next { (x, x_next) } else { (x_next, x) };
+        let xa_floor = xa.floor();
+        let xa_i = xa_floor as usize;
+        let xb_ceil = xb.ceil();
+        let xb_i = xb_ceil as usize;
+
+        if xb_i <= xa_i + 1 {
+            // the edge stays inside a single pixel on this row
+            let xmf = 0.5 * (x + x_next) - xa_floor;
+            line[xa_i] += d - d * xmf;
+            line[xa_i + 1] += d * xmf;
+        } else {
+            let s = (xb - xa).recip();
+            let xa_frac = xa - xa_floor;
+            let a0 = 0.5 * s * (1.0 - xa_frac) * (1.0 - xa_frac);
+            let xb_frac = xb - xb_ceil + 1.0;
+            let am = 0.5 * s * xb_frac * xb_frac;
+
+            line[xa_i] += d * a0;
+            if xb_i == xa_i + 2 {
+                line[xa_i + 1] += d * (1.0 - a0 - am);
+            } else {
+                let a1 = s * (1.5 - xa_frac);
+                line[xa_i + 1] += d * (a1 - a0);
+                for cell in &mut line[xa_i + 2..xb_i - 1] {
+                    *cell += d * s;
+                }
+                let a2 = a1 + (xb_i - xa_i - 3) as f32 * s;
+                line[xb_i - 1] += d * (1.0 - a2 - am);
+            }
+            line[xb_i] += d * am;
+        }
+
+        x = x_next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white() -> Srgb {
+        Srgb::new(1.0, 1.0, 1.0)
+    }
+    fn black() -> Srgb {
+        Srgb::new(0.0, 0.0, 0.0)
+    }
+
+    // each pixel's summed coverage from `fills`
+    fn coverage(rasterizer: &Rasterizer) -> Vec<f32> {
+        let (width, height) = (rasterizer.width, rasterizer.height);
+        let mut pixels = vec![[0.0; 4]; (width * height) as usize];
+        rasterizer.render_layer(&rasterizer.fills, 0, height, &mut pixels);
+        pixels.iter().map(|px| px[3]).collect()
+    }
+
+    fn render(rasterizer: &Rasterizer) -> Vec<u8> {
+        let (width, height) = (rasterizer.width, rasterizer.height);
+        let mut out = vec![0; (width * height * 4) as usize];
+        rasterizer.render_rows(0, height, &mut out);
+        out
+    }
+
+    #[test]
+    fn axis_aligned_edge_through_a_pixel() {
+        let half = Polygon::new_rect(Vec2::ZERO, vec2(1.5, 2.0));
+        let rasterizer = Rasterizer {
+            width: 3,
+            height: 2,
+            background: None,
+            outlines: Vec::new(),
+            fills: Shape::new(&half, Vec2::ONE, white()).into_iter().collect(),
+        };
+        assert_eq!(coverage(&rasterizer), [1.0, 0.5, 0.0, 1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn diagonal_neighbours_leave_no_seam() {
+        let mut bsp = bsp::Bsp::new(white());
+        bsp.split_at_point(vec2(0.5, 0.5), vec2(1.0, 0.7).normalize(), black());
+        bsp.split_at_point(vec2(0.3, 0.2), vec2(-0.2, 1.0).normalize(), white());
+        let rasterizer = Rasterizer::new(&bsp, 17, 13, &Style::default());
+        assert_eq!(rasterizer.fills.len(), 3);
+
+        for (i, total) in coverage(&rasterizer).into_iter().enumerate() {
+            assert!((total - 1.0).abs() < 1e-4, "pixel {} covered {}", i, total);
+        }
+    }
+
+    #[test]
+    fn solid_tree_is_uniform() {
+        let color = Srgb::new(0.2, 0.6, 0.9);
+        let mut bsp = bsp::Bsp::new(color);
+        bsp.split_at_point(vec2(0.5, 0.5), vec2(1.0, 0.3).normalize(), color);
+        bsp.split_at_point(vec2(0.2, 0.7), vec2(0.4, -1.0).normalize(), color);
+        bsp.split_at_point(vec2(0.8, 0.1), vec2(0.0, 1.0), color);
+
+        let expected = to_srgb8(color);
+        let pixels = render(&Rasterizer::new(&bsp, 31, 23, &Style::default()));
+        for px in pixels.chunks_exact(4) {
+            assert_eq!(px[..3], expected);
+            assert_eq!(px[3], 255);
+        }
+    }
+
+    #[test]
+    fn bands_join_up() {
+        let mut bsp = bsp::Bsp::new(white());
+        bsp.split_at_point(vec2(0.5, 0.5), vec2(1.0, 0.2).normalize(), black());
+        let red = Srgb::new(1.0, 0.0, 0.0);
+        bsp.split_at_point(vec2(0.5, 0.5), vec2(0.3, 1.0).normalize(), red);
+
+        // over two whole bands and a bit, and exactly one
+        for height in [2 * BAND_HEIGHT + 5, BAND_HEIGHT] {
+            let rasterizer = Rasterizer::new(&bsp, 20, height, &Style::default());
+            let mut png = Vec::new();
+            rasterizer.write_png(&mut png).unwrap();
+
+            let decoder = png::Decoder::new(png.as_slice());
+            let mut reader = decoder.read_info().unwrap();
+            let mut decoded = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut decoded).unwrap();
+            assert_eq!(decoded, render(&rasterizer));
+        }
+    }
+
+    #[test]
+    fn zero_sizes_are_errors() {
+        let bsp = bsp::Bsp::new(white());
+        assert!(save_png(&bsp, 0, 10, &Style::default()).is_err());
+        assert!(save_png(&bsp, 10, 0, &Style::default()).is_err());
+        assert!(save_png(&bsp, 0, 0, &Style::default()).is_err());
+        assert!(save_png(&bsp, 1, 1, &Style::default()).is_ok());
+    }
+}
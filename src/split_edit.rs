@@ -0,0 +1,207 @@
+use eframe::egui;
+use egui::{Color32, Pos2, Stroke};
+use glam::Vec2;
+use palette::Oklab;
+
+use wrong_track::{bsp, BspNode, Plane, Polygon, RegionCache};
+
+use crate::view::View;
+
+/// How close, in screen pixels, the pointer has to be to grab something.
+const GRAB_RADIUS: f32 = 8.0;
+/// How far the rotate handle sticks out from the middle of the cut, in
+/// screen pixels.
+const HANDLE_LENGTH: f32 = 40.0;
+
+/// Moving the plane of the selected inode after the fact.
+///
+/// Dragging the cut line slides it along its normal; dragging the handle
+/// sticking out of its middle turns it around that middle. Everything below
+/// stays put in the tree and just gets clipped again as the plane moves.
+#[derive(Default)]
+pub struct SplitEditor {
+    drag: Option<Drag>,
+}
+
+enum Drag {
+    // canvas coordinates, and the plane's distance, as of the drag start
+    Move { grabbed_at: Vec2, distance: f32 },
+    Rotate { pivot: Vec2 },
+}
+
+// the selected cut and its handle, on screen
+struct Handles {
+    ends: (Pos2, Pos2),
+    pivot: Pos2,
+    rotate: Pos2,
+}
+
+impl SplitEditor {
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Lets the pointer drag the plane at `key` around. Records an undo step
+    /// when a drag starts, through `record`, and returns whether the plane
+    /// moved.
+    #[allow(clippy::too_many_arguments)]
+    pub fn interact(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        view: View,
+        bsp: &mut bsp::Bsp<Oklab>,
+        regions: &RegionCache,
+        record: impl FnOnce(&bsp::Bsp<Oklab>),
+        key: bsp::BspKey,
+    ) -> bool {
+        let rect = response.rect;
+        let (plane, region) = match (bsp.get(key), regions.region(key)) {
+            (Some(BspNode::Inode(inode)), Some(region)) => (inode.plane.clone(), region),
+            _ => {
+                self.drag = None;
+                return false;
+            }
+        };
+        let handles = handles(&plane, region, view, rect);
+
+        let grab = |pos: Pos2| {
+            let handles = handles.as_ref()?;
+            if pos.distance(handles.rotate) <= GRAB_RADIUS {
+                Some(Drag::Rotate {
+                    pivot: view.screen_to_canvas(handles.pivot, rect),
+                })
+            } else if distance_to_segment(pos, handles.ends) <= GRAB_RADIUS {
+                Some(Drag::Move {
+                    grabbed_at: view.screen_to_canvas(pos, rect),
+                    distance: plane.distance,
+                })
+            } else {
+                None
+            }
+        };
+
+        if self.drag.is_some() {
+            ui.ctx().output().cursor_icon = egui::CursorIcon::Grabbing;
+        } else if response.hover_pos().and_then(grab).is_some() {
+            ui.ctx().output().cursor_icon = egui::CursorIcon::Grab;
+        }
+
+        if response.drag_started() && ui.input().pointer.primary_down() {
+            self.drag = response.interact_pointer_pos().and_then(grab);
+            if self.drag.is_some() {
+                // the whole drag is one undo step
+                record(bsp);
+            }
+        }
+        if !response.dragged() {
+            self.drag = None;
+        }
+
+        let (drag, pos) = match (&self.drag, response.interact_pointer_pos()) {
+            (Some(drag), Some(pos)) => (drag, view.screen_to_canvas(pos, rect)),
+            _ => return false,
+        };
+        let new_plane = match *drag {
+            Drag::Move {
+                grabbed_at,
+                distance,
+            } => {
+                let distance = distance + plane.normal.dot(pos - grabbed_at);
+                Plane {
+                    normal: plane.normal,
+                    distance: clamp_into(region, plane.normal, distance),
+                }
+            }
+            Drag::Rotate { pivot } => {
+                let normal = (pos - pivot).normalize_or_zero();
+                if normal == Vec2::ZERO {
+                    return false;
+                }
+                Plane {
+                    normal,
+                    distance: pivot.dot(normal),
+                }
+            }
+        };
+
+        if new_plane.normal == plane.normal && new_plane.distance == plane.distance {
+            return false;
+        }
+        match bsp.plane_mut(key) {
+            Some(plane) => {
+                *plane = new_plane;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Draws the cut line of the plane at `key` and its rotate handle.
+    pub fn paint(
+        &self,
+        painter: &egui::Painter,
+        view: View,
+        rect: egui::Rect,
+        bsp: &bsp::Bsp<Oklab>,
+        regions: &RegionCache,
+        key: bsp::BspKey,
+    ) {
+        let handles = match (bsp.get(key), regions.region(key)) {
+            (Some(BspNode::Inode(inode)), Some(region)) => {
+                handles(&inode.plane, region, view, rect)
+            }
+            _ => None,
+        };
+        let handles = match handles {
+            Some(handles) => handles,
+            None => return,
+        };
+
+        let (a, b) = handles.ends;
+        for (width, color) in [(4.0, Color32::BLACK), (2.0, Color32::WHITE)] {
+            let stroke = Stroke::new(width, color);
+            painter.line_segment([a, b], stroke);
+            painter.line_segment([handles.pivot, handles.rotate], stroke);
+        }
+        painter.circle(handles.rotate, 5.0, Color32::WHITE, Stroke::new(2.0, Color32::BLACK));
+    }
+}
+
+fn handles(plane: &Plane, region: &Polygon, view: View, rect: egui::Rect) -> Option<Handles> {
+    let (a, b) = region.cut_line(plane)?;
+    let pivot = view.canvas_to_screen((a + b) * 0.5, rect);
+    // the view doesn't rotate or stretch, so directions carry over as is
+    let rotate = pivot + egui::vec2(plane.normal.x, plane.normal.y) * HANDLE_LENGTH;
+
+    Some(Handles {
+        ends: (view.canvas_to_screen(a, rect), view.canvas_to_screen(b, rect)),
+        pivot,
+        rotate,
+    })
+}
+
+fn distance_to_segment(p: Pos2, (a, b): (Pos2, Pos2)) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_sq() > 0.0 {
+        ((p - a).dot(ab) / ab.length_sq()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
+
+// keeps a line with `normal` crossing `region`, so sliding it can't empty
+// out either side
+fn clamp_into(region: &Polygon, normal: Vec2, distance: f32) -> f32 {
+    let along = region.vertices.iter().map(|v| v.dot(normal));
+    let lo = along.clone().fold(f32::INFINITY, f32::min);
+    let hi = along.fold(f32::NEG_INFINITY, f32::max);
+
+    let margin = (hi - lo) * 0.001;
+    if lo + margin < hi - margin {
+        distance.clamp(lo + margin, hi - margin)
+    } else {
+        distance
+    }
+}
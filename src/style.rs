@@ -0,0 +1,72 @@
+//! Outlines and gaps between leaves.
+
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::geom::{Join, Polygon};
+
+/// How leaves are drawn beyond their fill: an outline around each, and a
+/// gutter between neighbours.
+///
+/// Lengths are in canvas units, where the long side of the canvas is 1 (see
+/// [`canvas_size`](crate::canvas_size)), so they scale with whatever size the
+/// picture is drawn at.
+///
+/// Every renderer builds its shapes through [`Style::outline_region`] and
+/// [`Style::fill_region`]: all the outlines in the outline color first, then
+/// every fill on top.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Style {
+    /// Width of the outline, centered on each leaf's (inset) edge. 0 for none.
+    pub outline_width: f32,
+    /// Color of the outline.
+    pub outline_color: Srgb,
+    /// How the outline goes around corners.
+    pub outline_join: Join,
+    /// How far each leaf is shrunk away from its neighbours. 0 for none.
+    pub gutter: f32,
+    /// What shows through the gutter; `None` leaves it transparent.
+    pub gutter_color: Option<Srgb>,
+}
+
+impl Default for Style {
+    /// No outline and no gutter: leaves drawn edge to edge.
+    fn default() -> Style {
+        Style {
+            outline_width: 0.0,
+            outline_color: Srgb::new(0.0, 0.0, 0.0),
+            outline_join: Join::Miter,
+            gutter: 0.0,
+            gutter_color: None,
+        }
+    }
+}
+
+impl Style {
+    /// The leaf at `region`, shrunk by the gutter. Outlines are centered on
+    /// this.
+    pub fn inset_region(&self, region: &Polygon) -> Polygon {
+        region.inset(self.gutter)
+    }
+
+    /// What to fill with the outline color for the leaf at `region`, if
+    /// there's an outline.
+    pub fn outline_region(&self, region: &Polygon) -> Option<Polygon> {
+        if self.outline_width <= 0.0 {
+            return None;
+        }
+
+        let outline = self
+            .inset_region(region)
+            .outset(self.outline_width * 0.5, self.outline_join);
+        (outline.vertices.len() >= 3).then_some(outline)
+    }
+
+    /// What to fill with the leaf's own color, drawn over every outline. May
+    /// come out empty when the outline or gutter eats the whole leaf.
+    pub fn fill_region(&self, region: &Polygon) -> Polygon {
+        self.inset_region(region)
+            .inset(self.outline_width.max(0.0) * 0.5)
+    }
+}
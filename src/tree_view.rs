@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+use eframe::egui;
+use egui::collapsing_header::CollapsingState;
+use palette::Oklab;
+
+use wrong_track::{bsp, BspNode};
+
+use crate::inspector::{hex, plane_text};
+
+/// An edit asked for from the tree view, for the app to carry out.
+pub enum TreeAction {
+    /// Turn an inode back into a leaf.
+    Collapse(bsp::BspKey),
+    /// Remove a node and everything under it; its sibling takes over.
+    Delete(bsp::BspKey),
+}
+
+/// The tree as an outline, from the root down. Only expanded inodes are
+/// walked, so big trees cost no more than what's on screen.
+#[derive(Default)]
+pub struct TreeView {
+    // the selection we last opened the outline up to
+    revealed: Option<bsp::BspKey>,
+}
+
+impl TreeView {
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        bsp: &bsp::Bsp<Oklab>,
+        selected: &mut Option<bsp::BspKey>,
+    ) -> Option<TreeAction> {
+        // a new selection (say, from the canvas) gets every split above it
+        // opened, so it's in view
+        let mut reveal = HashSet::new();
+        if *selected != self.revealed {
+            if let Some(key) = *selected {
+                reveal.extend(bsp.ancestors(key));
+                reveal.insert(key);
+            }
+            self.revealed = *selected;
+        }
+
+        let mut action = None;
+        let mut row = Row {
+            bsp,
+            selected,
+            reveal: &reveal,
+            action: &mut action,
+        };
+        row.show(ui, bsp.root_key(), "root");
+        action
+    }
+}
+
+struct Row<'a> {
+    bsp: &'a bsp::Bsp<Oklab>,
+    selected: &'a mut Option<bsp::BspKey>,
+    reveal: &'a HashSet<bsp::BspKey>,
+    action: &'a mut Option<TreeAction>,
+}
+
+impl Row<'_> {
+    fn show(&mut self, ui: &mut egui::Ui, key: bsp::BspKey, side: &str) {
+        match self.bsp.get(key) {
+            Some(BspNode::Inode(inode)) => {
+                let id = ui.make_persistent_id(key);
+                let mut state = CollapsingState::load_with_default_open(ui.ctx(), id, false);
+                if self.reveal.contains(&key) && Some(key) != *self.selected {
+                    state.set_open(true);
+                }
+
+                let (le, gt) = (inode.le, inode.gt);
+                let text = format!("{} {}", side, plane_text(&inode.plane));
+                state
+                    .show_header(ui, |ui| self.label(ui, key, text))
+                    .body(|ui| {
+                        self.show(ui, le, "le");
+                        self.show(ui, gt, "gt");
+                    });
+            }
+            Some(BspNode::Leaf(leaf)) => {
+                let color = leaf.0;
+                ui.horizontal(|ui| {
+                    // line up with the inodes' toggle buttons
+                    ui.add_space(ui.spacing().indent);
+                    egui::widgets::color_picker::show_color(
+                        ui,
+                        crate::vec_to_color(color),
+                        egui::vec2(16.0, 12.0),
+                    );
+                    self.label(ui, key, format!("{} {}", side, hex(color)));
+                });
+            }
+            None => {}
+        }
+    }
+
+    fn label(&mut self, ui: &mut egui::Ui, key: bsp::BspKey, text: String) {
+        let is_selected = *self.selected == Some(key);
+        let is_leaf = self.bsp.leaf_value(key).is_some();
+        let is_root = key == self.bsp.root_key();
+
+        let response = ui.selectable_label(is_selected, egui::RichText::new(text).monospace());
+        if is_selected && self.reveal.contains(&key) {
+            response.scroll_to_me(Some(egui::Align::Center));
+        }
+        if response.clicked() {
+            *self.selected = Some(key);
+        }
+
+        response.context_menu(|ui| {
+            if ui
+                .add_enabled(!is_leaf, egui::Button::new("Collapse"))
+                .on_hover_text("Merge everything under this split back into one leaf")
+                .clicked()
+            {
+                *self.action = Some(TreeAction::Collapse(key));
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(!is_root, egui::Button::new("Delete subtree"))
+                .on_hover_text("Remove this and everything under it; the other side of the split takes over")
+                .clicked()
+            {
+                *self.action = Some(TreeAction::Delete(key));
+                ui.close_menu();
+            }
+        });
+    }
+}
@@ -0,0 +1,69 @@
+use eframe::egui;
+use egui::{Pos2, Rect};
+use glam::Vec2;
+
+/// How far in or out the view can go. Past about 10^4, f32 canvas
+/// coordinates get too coarse to place a split where the cursor is.
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 1e4;
+
+/// Where the canvas is looking: which canvas point sits at the middle of the
+/// screen rect, and how much bigger than fit-to-rect it's drawn.
+///
+/// Everything that turns canvas coordinates into screen coordinates or back
+/// goes through here, so drawing and hit-testing always agree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    canvas: Vec2,
+    center: Vec2,
+    zoom: f32,
+}
+
+impl View {
+    /// The whole of a canvas spanning the origin to `canvas`, as big as
+    /// fits in the rect.
+    pub fn fit(canvas: Vec2) -> View {
+        View {
+            canvas,
+            center: canvas * 0.5,
+            zoom: 1.0,
+        }
+    }
+
+    // screen pixels per canvas unit, the same both ways so nothing gets
+    // stretched
+    fn scale(&self, rect: Rect) -> f32 {
+        let fit = Vec2::new(rect.width(), rect.height()) / self.canvas;
+        fit.min_element() * self.zoom
+    }
+
+    pub fn canvas_to_screen(&self, v: Vec2, rect: Rect) -> Pos2 {
+        let p = (v - self.center) * self.scale(rect);
+        rect.center() + egui::vec2(p.x, p.y)
+    }
+
+    /// What [`View::canvas_to_screen`] works with, for drawing somewhere it
+    /// can't be called: the canvas point at the middle of `rect`, and screen
+    /// pixels per canvas unit.
+    pub fn center_and_scale(&self, rect: Rect) -> (Vec2, f32) {
+        (self.center, self.scale(rect))
+    }
+
+    pub fn screen_to_canvas(&self, p: Pos2, rect: Rect) -> Vec2 {
+        let p = p - rect.center();
+        Vec2::new(p.x, p.y) / self.scale(rect) + self.center
+    }
+
+    /// Moves the canvas along with a drag of `delta` screen pixels.
+    pub fn pan(&mut self, delta: egui::Vec2, rect: Rect) {
+        self.center -= Vec2::new(delta.x, delta.y) / self.scale(rect);
+    }
+
+    /// Zooms by `factor`, keeping whatever is under `anchor` in place.
+    pub fn zoom_around(&mut self, anchor: Pos2, factor: f32, rect: Rect) {
+        let before = self.screen_to_canvas(anchor, rect);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let after = self.screen_to_canvas(anchor, rect);
+        self.center += before - after;
+    }
+}